@@ -1,15 +1,22 @@
-use std::{env, fs};
+use std::{env, fs, io};
+use std::io::Read;
 use std::path::Path;
 use std::process::Command;
 use std::time::Duration;
 use clap::{App, arg};
 use directories_next::ProjectDirs;
-use rpg_compiler::{Config};
-use rpg_compiler::user_output::CompileError;
-use simple_colors::{blue, green};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use rpg_compiler::{Config, Phase, Progress, Token};
+use rpg_compiler::ast::{pretty_print, Stmt};
+use rpg_compiler::user_output::Diagnostic;
+use project::{binary_name, Project, Script};
+use simple_colors::{blue, green, red};
 use spinner::{SpinnerHandle, SpinnerBuilder};
 use spinners::utils::spinner_data::SpinnerData;
 
+mod project;
+
 pub struct ColoredSpinner {
     handle: SpinnerHandle,
 }
@@ -54,20 +61,199 @@ impl ColoredSpinner {
     }
 }
 
+/// Shows a spinner during every phase of the compilation and writes the output of the phases in
+/// `emit` next to the binary at `output`, e.g. `rpg.tokens`. The tokens and the syntax tree are
+/// written as soon as they are made, so they are there even when the compilation fails.
+struct SpinnerProgress<'a> {
+    spinner: Option<(Phase, ColoredSpinner)>,
+    /// Print how long every phase took
+    verbose: bool,
+    emit: &'a [&'a str],
+    output: &'a Path
+}
+
+impl SpinnerProgress<'_> {
+    fn stop(&mut self) {
+        if let Some((_, sp)) = self.spinner.take() {
+            sp.stop();
+            println!();
+        }
+    }
+}
+
+impl Progress for SpinnerProgress<'_> {
+    fn start(&mut self, phase: Phase) {
+        // Type checking and generating run at the same time, only the last one gets a spinner
+        self.stop();
+        let message = match phase {
+            Phase::Reading => "Reading input...",
+            Phase::Tokenizing => "Tokenizing...",
+            Phase::Parsing => "Parsing...",
+            Phase::Passes => "Running passes...",
+            Phase::TypeChecking => "Type checking...",
+            Phase::Generating => "Generating..."
+        };
+        self.spinner = Some((phase, ColoredSpinner::new(message.to_string())));
+    }
+    
+    fn finish(&mut self, phase: Phase, duration: Duration) {
+        if matches!(self.spinner, Some((current, _)) if current == phase) {
+            self.stop();
+        }
+        if self.verbose {
+            println!("{:?} took {:?}", phase, duration);
+        }
+    }
+
+    /// One token per line, so they can be compared between versions of the compiler
+    fn tokens(&mut self, tokens: &[Token]) {
+        if self.emit.contains(&"tokens") {
+            let tokens: String = tokens.iter().map(|token| format!("{token}\n")).collect();
+            fs::write(self.output.with_extension("tokens"), tokens).expect_compile_error("Couldn't write the tokens.");
+        }
+    }
+
+    fn program(&mut self, program: &[Stmt]) {
+        if self.emit.contains(&"ast") {
+            fs::write(self.output.with_extension("ast"), pretty_print(program)).expect_compile_error("Couldn't write the syntax tree.");
+        }
+    }
+}
+
+/// Prints out an error message in red and exits
+macro_rules! cli_error {
+    ($( $arg: tt)*) => ({
+        let s = format!($($arg)*);
+        eprintln!("{}", red!(s));
+        std::process::exit(1)
+    })
+}
+
+/// Whether to print the underlying errors when the cli fails
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+trait CompileError<T> {
+    fn expect_compile_error(self, msg: &str) -> T;
+}
+
+impl<T, E: Display> CompileError<T> for Result<T, E> {
+    fn expect_compile_error(self, msg: &str) -> T {
+        match self {
+            Ok(t) => t,
+            Err(e) => if VERBOSE.load(Ordering::Relaxed) {
+                cli_error!("{}\n== VERBOSE OUTPUT ==\n{}", msg, e)
+            } else {
+                cli_error!("{}", msg)
+            }
+        }
+    }
+}
+
+impl<T> CompileError<T> for Option<T> {
+    fn expect_compile_error(self, msg: &str) -> T {
+        match self {
+            Some(t) => t,
+            None => cli_error!("{}", msg)
+        }
+    }
+}
+
+/// Pretty-prints the diagnostics of the given source file and exits
+fn report(file: &str, source: &str, diagnostics: Vec<Diagnostic>) -> ! {
+    warn(file, source, diagnostics);
+    std::process::exit(1)
+}
+
+/// Pretty-prints the diagnostics of the given source file
+fn warn(file: &str, source: &str, diagnostics: Vec<Diagnostic>) {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(file, source));
+    }
+}
+
+/// Returns true if the file starts with a shebang line (`#!/usr/local/bin/rpgc`)
+fn is_script(file: &str) -> bool {
+    let mut start = [0; 2];
+    fs::File::open(file).and_then(|mut f| f.read_exact(&mut start)).is_ok() && &start == b"#!"
+}
+
+/// Compiles and runs a program that is executed through its shebang line, e.g. `./99bottles.rpg`.
+/// The binary is cached by the hash of the source code, so running the same program again starts
+/// it immediately.
+fn run_script(data_dir: &Path, file: &str, args: &[String]) -> ! {
+    let source = fs::read_to_string(file).expect_compile_error(&format!("{file} could not be found."));
+    // Kept until the program exits, so `rpgc clean` doesn't remove the binary while it runs
+    let script = Script::open(data_dir, &source, wait_for_other_process)
+        .expect_compile_error("Couldn't create cache directory.");
+    if !script.binary().exists() {
+        let compiled = rpg_compiler::compile_str(&source, file, Config::default())
+            .unwrap_or_else(|diagnostics| report(file, &source, diagnostics));
+        warn(file, &source, compiled.warnings);
+        let project = Project::open(data_dir, &compiled.code, true, None, wait_for_other_process)
+            .expect_compile_error("Couldn't create working directory.");
+        if !project.is_built() {
+            let o = project.build().expect_compile_error("Failed to execute rust compiler");
+            if !o.status.success() {
+                cli_error!("Couldn't compile the generated rust code.\n{}", String::from_utf8_lossy(&o.stderr).trim())
+            }
+        }
+        script.cache(&project.binary()).expect_compile_error("Couldn't write to the cache directory.");
+    }
+    let status = Command::new(script.binary())
+        .args(args)
+        .status()
+        .expect_compile_error("Failed to run the compiled program");
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+/// Reads the source file, or stdin when the file is `-`. Returns the name to use in diagnostics
+/// with the source code.
+fn read_source(file: &str) -> (&str, String) {
+    if file == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).expect_compile_error("Could not read the source code from stdin.");
+        ("<stdin>", source)
+    } else {
+        (file, fs::read_to_string(file).expect_compile_error(&format!("{file} could not be found.")))
+    }
+}
+
+/// Tells the user why the compilation doesn't continue, another process is using its build
+fn wait_for_other_process() {
+    eprintln!("Waiting for another rpgc process to finish...");
+}
+
 fn main() {
     let dir = ProjectDirs::from("be", "jonaseveraert", "rpgc").expect("No valid home directory path could be retrieved from the operating system");
     let data_dir = dir.data_dir();
+    
+    // Executed as the interpreter of a program, all other arguments are for the program
+    let args: Vec<String> = env::args().collect();
+    if let Some(file) = args.get(1) {
+        if is_script(file) {
+            run_script(data_dir, file, &args[2..])
+        }
+    }
+    
     let matches = App::new("RPG Compiler")
         .version("0.1.0")
         .author("Jonas Everaert <info@jonaseveraert.be>")
         .about("The official compiler for the RPG esoteric programming language")
-        .arg(arg!([file] "The .rpg source file you wish to compile"))
+        .arg(arg!([file] "The .rpg source file you wish to compile, use - to read it from stdin"))
         .arg(
             arg!([output_dir] "Sets the output directory of the compiled app")
         )
+        .arg(
+            arg!(-o --output <NAME> "Sets the name of the compiled app (default: rpg)")
+                .required(false)
+        )
         .arg(
             arg!(-r --release "Compiles the program with optimizations")
         )
+        .arg(
+            arg!(--target <TRIPLE> "Compiles the program for another platform, e.g. x86_64-pc-windows-gnu. The target has to be installed using rustup")
+                .required(false)
+        )
         .arg(
             arg!(-d --debug "Compiles the program without optimization (default)")
         )
@@ -78,15 +264,43 @@ fn main() {
         .arg(
             arg!(-v --verbose "Prints out more error messages")
         )
+        .arg(
+            arg!(--no_fold "Generates code for every statement, instead of running the statements before the first input at compile time")
+        )
+        .arg(
+            arg!(--emit <KINDS> "Writes the tokens, the checked syntax tree, the generated rust code and/or the binary next to the output (default: bin)")
+                .required(false)
+                .possible_values(["tokens", "ast", "rust", "bin"])
+                .use_delimiter(true)
+                .multiple_occurrences(true)
+        )
         .subcommand(
             App::new("clean")
-                .about("Cleans the build folder"),
+                .about("Cleans the build folder, or only the builds matching the options")
+                .arg(
+                    arg!(--older_than <DAYS> "Only removes the builds and scripts that weren't used for this amount of days")
+                        .required(false)
+                )
+                .arg(
+                    arg!(--max_size <MB> "Removes the least recently used builds and scripts until the rest takes up at most this amount of megabytes")
+                        .required(false)
+                ),
+        )
+        .subcommand(
+            App::new("run")
+                .about("Runs the program directly, without compiling it to a binary")
+                .arg(arg!(<file> "The .rpg source file you wish to run, use - to read it from stdin"))
+                .arg(
+                    arg!(-m --max_char <VALUE> "Optionally sets the maximum amount of characters allowed in the program")
+                        .required(false)
+                ),
         )
         .get_matches();
     
     if let Some(file) = matches.value_of("file") {
         let debug = !matches.is_present("release");
         let verbose = matches.is_present("verbose");
+        VERBOSE.store(verbose, Ordering::Relaxed);
         let max_char = matches.value_of("max_char");
         let _output_dir = matches.value_of("output_dir");
         let cd = env::current_dir().expect_compile_error("Could not find current working directory");
@@ -96,81 +310,68 @@ fn main() {
         } else {
             output_dir = cd.as_path()
         };
-        let app_name = "rpg"; // TODO: argument for app name
+        let target = matches.value_of("target");
+        let output = output_dir.join(matches.value_of("output").unwrap_or(binary_name(target)));
         
-        let compiled = if verbose == true || max_char.is_some() {
-            unsafe {
-                rpg_compiler::compile_with_config(
-                    file,
-                    Config {
-                        max_char: if max_char.is_some() { max_char.unwrap().parse::<usize>().expect_compile_error("Did not specify a valid number for max_char") } else { 10 },
-                        verbose
-                    },
-                )
-            }
-        } else {
-            rpg_compiler::compile(file)
+        let config = Config {
+            max_char: if let Some(max_char) = max_char { max_char.parse::<usize>().expect_compile_error("Did not specify a valid number for max_char") } else { 10 },
+            verbose,
+            fold: !matches.is_present("no_fold")
         };
+        if config.max_char > 10 { println!("Cheater :(") }
+        let (file, source) = read_source(file);
+        let emit: Vec<&str> = matches.values_of("emit").map(|kinds| kinds.collect()).unwrap_or_else(|| vec!["bin"]);
+        let mut progress = SpinnerProgress { spinner: None, verbose, emit: &emit, output: &output };
+        let compiled = rpg_compiler::compile_str_with_progress(&source, file, config, &mut progress)
+            .unwrap_or_else(|diagnostics| report(file, &source, diagnostics));
+        warn(file, &source, compiled.warnings);
         
-        let compiled_path = Path::new(data_dir).join("tmp_compiled");
-        
-        if !compiled_path.exists() {
-            fs::create_dir_all(&compiled_path).expect_compile_error("Couldn't create working directory.");
-            fs::create_dir(&compiled_path.join("src")).expect_compile_error("Couldn't create working directory.");
-            fs::write(&compiled_path.join("Cargo.toml"), CARGO_TOML).expect_compile_error("Couldn't create working directory.");
+        if emit.contains(&"rust") {
+            fs::write(output.with_extension("rs"), &compiled.code).expect_compile_error("Couldn't write the generated rust code.");
+        }
+        if !emit.contains(&"bin") {
+            println!("\n{}", blue!("Compilation successful."));
+            return;
         }
         
-        fs::write(&compiled_path.join("src").join("main.rs"), compiled).expect_compile_error("Couldn't write compiled source file.");
-    
-        let sp = ColoredSpinner::new("Compiling rust project...".to_string());
-        let o = if cfg!(target_os = "windows") {
-            Command::new("cmd")
-                .args(["/C", &format!("cd \"{}\" && cargo build{}", compiled_path.to_str().expect_compile_error(
-                    "unable to convert compiled path to string."
-                    ),
-                    if debug {""} else {" --release"}
-                ).trim()])
-                .output()
-                .expect_compile_error("Failed to execute rust compiler")
-        } else {
-            Command::new("sh")
-                .arg("-c")
-                .arg(&format!("cd \"{}\" && cargo build{}", compiled_path.to_str().expect_compile_error(
-                    "unable to convert compiled path to string."
-                    ),
-                    if debug {""} else {" --release"}
-                ).trim())
-                .output()
-                .expect_compile_error("Failed to execute rust compiler")
-        };
-        sp.stop();
-    
-        if verbose {
-            let out = String::from_utf8(o.stdout).expect("Couldn't convert utf8.");
-            let out = out.trim();
-            let err = String::from_utf8(o.stderr).expect("Couldn't convert utf8.");
-            let err = err.trim();
-            println!("{out}");
-            println!("{err}");
+        let project = Project::open(data_dir, &compiled.code, debug, target, wait_for_other_process)
+            .expect_compile_error("Couldn't create working directory.");
+        
+        // The same program was already built with the same options
+        if !project.is_built() {
+            let sp = ColoredSpinner::new("Compiling rust project...".to_string());
+            let o = project.build().expect_compile_error("Failed to execute rust compiler");
+            sp.stop();
+            if !o.status.success() {
+                cli_error!("Couldn't compile the generated rust code.\n{}", String::from_utf8_lossy(&o.stderr).trim())
+            }
+        
+            if verbose {
+                let out = String::from_utf8(o.stdout).expect("Couldn't convert utf8.");
+                let out = out.trim();
+                let err = String::from_utf8(o.stderr).expect("Couldn't convert utf8.");
+                let err = err.trim();
+                println!("{out}");
+                println!("{err}");
+            }
         }
         
         // Copy compiled to output_dir
         println!();
         let sp = ColoredSpinner::new("Copying...".to_string());
-        let output_dir = Path::new(output_dir);
-        let compiled_file_dir = compiled_path.join("target").join("debug").join(app_name);
-        fs::write(output_dir.join(app_name), fs::read(compiled_file_dir).expect_compile_error("Compiled file not found or no read access.")).expect_compile_error("Couldn't write output file.");
+        fs::copy(project.binary(), &output).expect_compile_error("Compiled file not found or no read access.");
     
         sp.message("Setting file permissions...".to_string());
         let o = if cfg!(target_os = "windows") {
-            Command::new("cmd")
-                .args(["/C", &format!("icacls {app_name} /grant user:(gw,ge,d,wd,ra,rea)")])
+            Command::new("icacls")
+                .arg(&output)
+                .args(["/grant", "user:(gw,ge,d,wd,ra,rea)"])
                 .output()
                 .expect_compile_error("Failed to execute rust compiler")
         } else {
-            Command::new("sh")
-                .arg("-c")
-                .arg(&format!("chmod +x {app_name}"))
+            Command::new("chmod")
+                .arg("+x")
+                .arg(&output)
                 .output()
                 .expect_compile_error("Failed to execute rust compiler")
         };
@@ -182,17 +383,30 @@ fn main() {
         }
         println!("\n{}", blue!("Compilation successful."))
     }
-    else if let Some(("clean", _)) = matches.subcommand() {
-        fs::remove_dir_all(data_dir).expect("Couldn't remove working directory.");
-        println!("{}", green!("Cleaned build folder"))
+    else if let Some(("run", matches)) = matches.subcommand() {
+        let file = matches.value_of("file").expect("file is a required argument");
+        let max_char = matches.value_of("max_char")
+            .map(|max_char| max_char.parse::<usize>().expect_compile_error("Did not specify a valid number for max_char"))
+            .unwrap_or(10);
+        let config = Config { max_char, ..Config::default() };
+        let (file, source) = read_source(file);
+        if let Err(diagnostics) = rpg_compiler::interpret_str(&source, config, |warning| warn(file, &source, vec![warning])) {
+            report(file, &source, diagnostics)
+        }
+    }
+    else if let Some(("clean", matches)) = matches.subcommand() {
+        let older_than = matches.value_of("older_than")
+            .map(|days| days.parse::<u64>().expect_compile_error("Did not specify a valid number of days"))
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+        let max_size = matches.value_of("max_size")
+            .map(|mb| mb.parse::<u64>().expect_compile_error("Did not specify a valid size"))
+            .map(|mb| mb * 1024 * 1024);
+        let everything = older_than.is_none() && max_size.is_none();
+        let older_than = if everything { Some(Duration::ZERO) } else { older_than };
+        let pruned = project::prune(data_dir, older_than, max_size, wait_for_other_process)
+            .expect_compile_error("Couldn't remove working directory.");
+        println!("{}", green!(format!("Removed {} builds ({} MB)", pruned.removed, pruned.freed / (1024 * 1024))))
     } else {
         println!("Please specify a source file")
     }
 }
-
-/// The cargo.toml for the tmp_compiled dir
-const CARGO_TOML: &str =
-r#"[package]
-name = "rpg"
-version = "0.1.0"
-edition = "2021""#;
@@ -0,0 +1,282 @@
+//! The cargo projects the generated code is built in.
+//!
+//! Every build gets its own project in `builds/<hash>` of the data dir, keyed by the generated
+//! code and the build options. Compilations running at the same time don't overwrite each
+//! other's code, and a program that was built before is not built again.
+//!
+//! Every project contains a copy of the runtime the cli was built with, so programs are built
+//! without downloading it.
+//!
+//! Programs that run through their shebang line cache their binary in `scripts/<hash>`.
+//!
+//! Every project has a lock file that keeps two compilations from using it at once. Compilations
+//! and scripts also share the lock of the data dir, which `prune` locks exclusively, so projects
+//! and scripts are never removed while they are used.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, TryLockError};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+
+/// The cargo.toml of every project
+const CARGO_TOML: &str =
+r#"[package]
+name = "rpg"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+rpg-runtime = { path = "runtime" }
+"#;
+
+/// The cargo.toml of the runtime in every project, its source code is [`rpg_runtime::SOURCE`]
+const RUNTIME_CARGO_TOML: &str =
+r#"[package]
+name = "rpg-runtime"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+"#;
+
+/// The lock file of the data dir and of every project
+const LOCK: &str = ".lock";
+/// Written every time a project or script is used, its modification time is used to prune them
+const LAST_USED: &str = ".last_used";
+
+/// A locked cargo project, other compilations of the same code wait until it is dropped
+pub struct Project {
+    path: PathBuf,
+    debug: bool,
+    target: Option<String>,
+    /// Unlocked when the project is dropped
+    _lock: File,
+    /// Shared with other compilations, keeps `prune` from removing the project
+    _data_lock: File
+}
+
+impl Project {
+    /// Opens the project of the code and build options, creating it when it doesn't exist yet.
+    /// `on_wait` is called before waiting for another compilation that uses the project.
+    pub fn open(data_dir: &Path, code: &str, debug: bool, target: Option<&str>, mut on_wait: impl FnMut()) -> io::Result<Project> {
+        let mut hasher = DefaultHasher::new();
+        (env!("CARGO_PKG_VERSION"), code, debug, target, rpg_runtime::SOURCE).hash(&mut hasher);
+        let path = data_dir.join("builds").join(format!("{:016x}", hasher.finish()));
+        let data_lock = lock_shared(data_dir, &mut on_wait)?;
+        // Created after locking the data dir, so it can't be pruned in the meantime
+        fs::create_dir_all(path.join("src"))?;
+        fs::create_dir_all(path.join("runtime").join("src"))?;
+        let lock = open_lock(&path)?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                on_wait();
+                lock.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(e)
+        }
+        fs::write(path.join(LAST_USED), "")?;
+        let project = Project { path, debug, target: target.map(str::to_string), _lock: lock, _data_lock: data_lock };
+        if !project.is_built() {
+            fs::write(project.path.join("Cargo.toml"), CARGO_TOML)?;
+            fs::write(project.path.join("src").join("main.rs"), code)?;
+            fs::write(project.path.join("runtime").join("Cargo.toml"), RUNTIME_CARGO_TOML)?;
+            fs::write(project.path.join("runtime").join("src").join("lib.rs"), rpg_runtime::SOURCE)?;
+        }
+        Ok(project)
+    }
+
+    /// Whether the binary was already built by an earlier compilation
+    pub fn is_built(&self) -> bool {
+        self.binary().exists()
+    }
+
+    /// Runs `cargo build` in the project, optionally cross-compiling for the target triple
+    pub fn build(&self) -> io::Result<Output> {
+        let mut cargo = Command::new("cargo");
+        cargo.arg("build").current_dir(&self.path);
+        if !self.debug {
+            cargo.arg("--release");
+        }
+        if let Some(target) = &self.target {
+            cargo.args(["--target", target]);
+        }
+        cargo.output()
+    }
+
+    /// The binary built by `build`, cargo puts it in a directory per target and profile
+    pub fn binary(&self) -> PathBuf {
+        let mut dir = self.path.join("target");
+        if let Some(target) = &self.target {
+            dir = dir.join(target);
+        }
+        dir.join(if self.debug { "debug" } else { "release" }).join(binary_name(self.target.as_deref()))
+    }
+}
+
+/// The file name of the binary, with `.exe` when it is built for windows
+pub fn binary_name(target: Option<&str>) -> &'static str {
+    let windows = target.map_or(cfg!(target_os = "windows"), |target| target.contains("windows"));
+    if windows { "rpg.exe" } else { "rpg" }
+}
+
+/// The cached binary of a program that runs through its shebang line
+pub struct Script {
+    path: PathBuf,
+    /// Shared with compilations and other scripts, keeps `prune` from removing the binary
+    _data_lock: File
+}
+
+impl Script {
+    /// Opens the cache of the source code. `on_wait` is called before waiting for `prune`.
+    pub fn open(data_dir: &Path, source: &str, mut on_wait: impl FnMut()) -> io::Result<Script> {
+        let mut hasher = DefaultHasher::new();
+        (env!("CARGO_PKG_VERSION"), source).hash(&mut hasher);
+        let path = data_dir.join("scripts").join(format!("{:016x}", hasher.finish()));
+        let data_lock = lock_shared(data_dir, &mut on_wait)?;
+        fs::create_dir_all(&path)?;
+        fs::write(path.join(LAST_USED), "")?;
+        Ok(Script { path, _data_lock: data_lock })
+    }
+
+    /// The cached binary, it only exists after `cache` was called
+    pub fn binary(&self) -> PathBuf {
+        self.path.join(binary_name(None))
+    }
+
+    /// Copies the binary into the cache
+    pub fn cache(&self, binary: &Path) -> io::Result<()> {
+        // Renamed into place, so the same script running at the same time never sees half a binary
+        let copy = self.path.join(format!("rpg.{}", std::process::id()));
+        fs::copy(binary, &copy)?;
+        fs::rename(copy, self.binary())
+    }
+}
+
+/// What `prune` removed
+#[derive(Debug, Default)]
+pub struct Pruned {
+    pub removed: usize,
+    /// The size of the removed projects and scripts in bytes
+    pub freed: u64
+}
+
+/// Opens the lock file in the directory
+fn open_lock(dir: &Path) -> io::Result<File> {
+    File::options().create(true).truncate(false).write(true).open(dir.join(LOCK))
+}
+
+/// Shares the lock of the data dir, `on_wait` is called before waiting for `prune`
+fn lock_shared(data_dir: &Path, on_wait: &mut impl FnMut()) -> io::Result<File> {
+    fs::create_dir_all(data_dir)?;
+    let lock = open_lock(data_dir)?;
+    match lock.try_lock_shared() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            on_wait();
+            lock.lock_shared()?;
+        }
+        Err(TryLockError::Error(e)) => return Err(e)
+    }
+    Ok(lock)
+}
+
+/// Removes the projects and scripts that weren't used for `older_than`, then removes the least
+/// recently used ones until all of them together are no larger than `max_size` bytes. `on_wait` is
+/// called before waiting for the compilations and scripts that are running.
+pub fn prune(data_dir: &Path, older_than: Option<Duration>, max_size: Option<u64>, on_wait: impl FnOnce()) -> io::Result<Pruned> {
+    let mut pruned = Pruned::default();
+    // The single project of older versions of the cli is never used again
+    let old_project = data_dir.join("tmp_compiled");
+    if old_project.exists() {
+        pruned.freed += size(&old_project)?;
+        pruned.removed += 1;
+        fs::remove_dir_all(old_project)?;
+    }
+    if !data_dir.exists() {
+        return Ok(pruned);
+    }
+    let data_lock = open_lock(data_dir)?;
+    match data_lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            on_wait();
+            data_lock.lock()?;
+        }
+        Err(TryLockError::Error(e)) => return Err(e)
+    }
+    let mut projects = Vec::new();
+    for dir in ["builds", "scripts"] {
+        let dir = data_dir.join(dir);
+        if !dir.exists() {
+            continue;
+        }
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+            let last_used = fs::metadata(path.join(LAST_USED)).or_else(|_| fs::metadata(&path))?.modified()?;
+            projects.push((last_used, size(&path)?, path));
+        }
+    }
+    // The least recently used project first
+    projects.sort();
+    let mut total: u64 = projects.iter().map(|(_, size, _)| size).sum();
+    let now = SystemTime::now();
+    for (last_used, size, path) in projects {
+        let too_old = older_than.is_some_and(|older_than| now.duration_since(last_used).unwrap_or_default() >= older_than);
+        let too_large = max_size.is_some_and(|max_size| total > max_size);
+        if too_old || too_large {
+            fs::remove_dir_all(path)?;
+            total -= size;
+            pruned.freed += size;
+            pruned.removed += 1;
+        }
+    }
+    Ok(pruned)
+}
+
+/// The size of all files in the directory in bytes
+fn size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() { self::size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::Stdio;
+    use rpg_compiler::{compile_str, Config};
+
+    #[test]
+    fn builds_programs_with_the_embedded_runtime() {
+        let data_dir = std::env::temp_dir().join(format!("rpgc-test-{}", std::process::id()));
+        let source = "char a = (5, 1)\nspellbook sb = ()\nmerchant m = ()\na buys sb from m\na uses sb casting god_speech()\na shouts";
+        // Not folded, so the runtime is used to read the input
+        let config = Config { fold: false, ..Config::default() };
+        let code = compile_str(source, "test.rpg", config).unwrap_or_else(|e| panic!("{e:?}")).code;
+        let project = Project::open(&data_dir, &code, true, None, || {}).unwrap();
+        let output = project.build().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(fs::read_to_string(project.path.join("runtime").join("src").join("lib.rs")).unwrap(), rpg_runtime::SOURCE);
+
+        let mut program = Command::new(project.binary()).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        program.stdin.as_mut().unwrap().write_all(b"42\n").unwrap();
+        let output = program.wait_with_output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+
+        drop(project);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+//! Tokenizes a generated program of 100 000 lines.
+//!
+//! Run with `cargo bench --bench tokenizer`.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use rpg_compiler::Tokenizer;
+
+const LINES: usize = 100_000;
+const RUNS: usize = 10;
+
+/// Generates a program using every kind of token
+fn generate_program() -> String {
+    let mut program = String::from("merchant m = ()\nspellbook sb = ()\npotion p = (5)\n");
+    let mut lines = 3;
+    let mut i = 0;
+    while lines < LINES {
+        program.push_str(&format!(
+            "char hero_{i} = ({health}, 3)\n\
+            zombie walker_{i} = (-{health}, 1)\n\
+            hero_{i} buys sb from m\n\
+            hero_{i} buys p from m\n\
+            walker_{i} attacks hero_{i}\n\
+            hero_{i} uses p\n\
+            hero_{i} shouts\n\
+            hero_{i} whispers sb casting speak()\n\
+            hero_{i} uses sb casting time_warp(hero_{i})\n\
+            \thero_{i} uses sb casting un_zombify(walker_{i})\n\
+            end\n",
+            health = i % 100 + 1
+        ));
+        lines += 11;
+        i += 1;
+    }
+    program
+}
+
+fn main() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("bench_100k.rpg");
+    fs::write(&path, generate_program()).expect("Could not write the generated program.");
+    let code = fs::read_to_string(&path).expect("Could not read the generated program.");
+    
+    let mut times: Vec<Duration> = Vec::with_capacity(RUNS);
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = Tokenizer::new(&code).tokenize().expect("The generated program contains unknown tokens.").len();
+        times.push(start.elapsed());
+    }
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / RUNS as u32;
+    println!("{}: {} lines, {} bytes, {} tokens", path.display(), code.lines().count(), code.len(), tokens);
+    println!("tokenize: min {:?}, median {:?}, mean {:?} over {} runs", times[0], times[RUNS / 2], mean, RUNS);
+}
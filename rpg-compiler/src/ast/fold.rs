@@ -0,0 +1,111 @@
+//! Rewrites the syntax tree by taking it apart and building a new one.
+//!
+//! Override the methods for the statements you want to change. To keep folding the body of a
+//! time warp from an overridden method, call the matching `fold_*` function.
+
+use crate::ast::{Action, Declaration, Spell, SpellKind, Stmt};
+
+pub trait Fold {
+    /// Folds a list of statements, either the whole program or the body of a time warp
+    fn fold_stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        fold_stmts(self, stmts)
+    }
+
+    /// Returns the statements that replace `stmt`. Returning no statements removes it.
+    fn fold_stmt(&mut self, stmt: Stmt) -> Vec<Stmt> {
+        vec![fold_stmt(self, stmt)]
+    }
+
+    fn fold_declaration(&mut self, declaration: Declaration) -> Declaration {
+        declaration
+    }
+
+    fn fold_action(&mut self, action: Action) -> Action {
+        action
+    }
+
+    /// Folds the spell and then the body of a time warp
+    fn fold_spell(&mut self, spell: Spell) -> Spell {
+        fold_spell(self, spell)
+    }
+}
+
+pub fn fold_stmts<F: Fold + ?Sized>(folder: &mut F, stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts.into_iter().flat_map(|stmt| folder.fold_stmt(stmt)).collect()
+}
+
+pub fn fold_stmt<F: Fold + ?Sized>(folder: &mut F, stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Declaration(declaration) => Stmt::Declaration(folder.fold_declaration(declaration)),
+        Stmt::Action(action) => Stmt::Action(folder.fold_action(action)),
+        Stmt::Spell(spell) => Stmt::Spell(folder.fold_spell(spell))
+    }
+}
+
+pub fn fold_spell<F: Fold + ?Sized>(folder: &mut F, spell: Spell) -> Spell {
+    match spell.kind {
+        SpellKind::TimeWarp { consumed, body } => Spell {
+            kind: SpellKind::TimeWarp { consumed, body: folder.fold_stmts(body) },
+            ..spell
+        },
+        _ => spell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ActionKind;
+    use crate::test_util::{actions, parse};
+
+    const PROGRAM: &str = "char a = (1, 1)\nchar b = (1, 1)\nspellbook sb = ()\na whispers\n\
+    a uses sb casting time_warp(b)\n\ta whispers\n\ta shouts\nend";
+
+    /// Turns every whisper into a shout
+    struct Louder;
+
+    impl Fold for Louder {
+        fn fold_action(&mut self, action: Action) -> Action {
+            match action.kind {
+                ActionKind::Whispers { user } => Action { kind: ActionKind::Shouts { user }, ..action },
+                _ => action
+            }
+        }
+    }
+
+    #[test]
+    fn rewrites_nested_statements() {
+        let program = Louder.fold_stmts(parse(PROGRAM));
+        let shout = ActionKind::Shouts { user: 1 };
+        assert_eq!(actions(&program), [(0, shout), (1, shout), (1, shout)]);
+    }
+
+    /// Removes every shout and repeats every whisper
+    struct Quiet;
+
+    impl Fold for Quiet {
+        fn fold_stmt(&mut self, stmt: Stmt) -> Vec<Stmt> {
+            match &stmt {
+                Stmt::Action(Action { kind: ActionKind::Shouts { .. }, .. }) => vec![],
+                Stmt::Action(Action { kind: ActionKind::Whispers { .. }, .. }) => vec![stmt.clone(), stmt],
+                _ => vec![fold_stmt(self, stmt)]
+            }
+        }
+    }
+
+    #[test]
+    fn removes_and_adds_nested_statements() {
+        let program = Quiet.fold_stmts(parse(PROGRAM));
+        let whisper = ActionKind::Whispers { user: 1 };
+        assert_eq!(actions(&program), [(0, whisper), (0, whisper), (1, whisper), (1, whisper)]);
+        assert_eq!(program.len(), 6);
+    }
+
+    #[test]
+    fn default_fold_keeps_the_program() {
+        struct Identity;
+        impl Fold for Identity {}
+        let program = parse(PROGRAM);
+        assert_eq!(Identity.fold_stmts(program.clone()), program);
+    }
+}
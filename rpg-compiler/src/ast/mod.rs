@@ -0,0 +1,136 @@
+//! The syntax tree of an RPG program, as produced by the [`Parser`](crate::Parser).
+//!
+//! Objects are referred to by the id of their declaration.
+
+use crate::user_output::Span;
+
+pub mod visit;
+pub use visit::Visitor;
+pub mod fold;
+pub use fold::Fold;
+pub mod print;
+pub use print::pretty_print;
+
+/// A single statement of a program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    /// `char a = (1, 2)`
+    Declaration(Declaration),
+    /// `a attacks b`
+    Action(Action),
+    /// `a uses sb casting confuse(b)`
+    Spell(Spell)
+}
+
+impl Stmt {
+    /// The source code the statement was parsed from
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Declaration(declaration) => declaration.span,
+            Stmt::Action(action) => action.span,
+            Stmt::Spell(spell) => spell.span
+        }
+    }
+
+    /// The id of the character or zombie the statement declares
+    pub fn declared_actor(&self) -> Option<usize> {
+        match self {
+            Stmt::Declaration(declaration) if declaration.object.kind().is_actor() => Some(declaration.id),
+            _ => None
+        }
+    }
+}
+
+/// Creates a new named object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub id: usize,
+    pub name: String,
+    pub object: Object,
+    pub span: Span
+}
+
+/// An object and its initial values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Object {
+    Char { health: u32, attack: u32 },
+    Zombie { health: i32, attack: u32 },
+    Merchant,
+    Potion { value: u32 },
+    SpellBook
+}
+
+impl Object {
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            Object::Char { .. } => ObjectKind::Char,
+            Object::Zombie { .. } => ObjectKind::Zombie,
+            Object::Merchant => ObjectKind::Merchant,
+            Object::Potion { .. } => ObjectKind::Potion,
+            Object::SpellBook => ObjectKind::SpellBook
+        }
+    }
+}
+
+/// The type of an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Char,
+    Zombie,
+    Merchant,
+    Potion,
+    SpellBook
+}
+
+impl ObjectKind {
+    /// Characters and zombies are actors
+    pub fn is_actor(&self) -> bool {
+        matches!(self, ObjectKind::Char | ObjectKind::Zombie)
+    }
+}
+
+/// An action of an actor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    pub kind: ActionKind,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionKind {
+    /// `user buys item from merchant`
+    Buys { user: usize, item: usize, merchant: usize },
+    /// `attacker attacks attacked`
+    Attacks { attacker: usize, attacked: usize },
+    /// `user uses potion`
+    Uses { user: usize, potion: usize },
+    /// `user shouts`
+    Shouts { user: usize },
+    /// `user whispers`
+    Whispers { user: usize },
+    /// `user shouts spell_book casting speak()`
+    ShoutsSpeak { user: usize, spell_book: usize },
+    /// `user whispers spell_book casting speak()`
+    WhispersSpeak { user: usize, spell_book: usize }
+}
+
+/// `user uses spell_book casting spell()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spell {
+    pub user: usize,
+    pub spell_book: usize,
+    pub kind: SpellKind,
+    pub span: Span
+}
+
+/// Spell book functions, speak is always in a shouts or whispers action
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpellKind {
+    UnZombify { zombie: usize },
+    Confuse { actor: usize },
+    GodSpeech,
+    /// Runs the body until the consumed character has no health left
+    TimeWarp { consumed: usize, body: Vec<Stmt> },
+    Shift,
+    CreatePotion { potion: usize }
+}
@@ -0,0 +1,83 @@
+//! Prints the syntax tree in the syntax of the language, one statement per line.
+//!
+//! Every object is printed as its name followed by the id of its declaration, e.g. `ash#1`, so
+//! the output shows which declaration a name resolved to. Spans and comments are left out, so
+//! the output only changes when the meaning of the program changes.
+
+use std::collections::HashMap;
+use crate::ast::{Action, ActionKind, Declaration, Object, Spell, SpellKind, Stmt};
+use crate::ast::visit::{self, Visitor};
+
+/// Prints the program, the body of a time warp is indented by 4 spaces
+pub fn pretty_print(program: &[Stmt]) -> String {
+    let mut printer = Printer::default();
+    printer.visit_stmts(program);
+    printer.output
+}
+
+#[derive(Default)]
+struct Printer<'ast> {
+    output: String,
+    /// The name of every declaration printed so far
+    names: HashMap<usize, &'ast str>,
+    /// The amount of time warps around the current statement
+    depth: usize
+}
+
+impl<'ast> Printer<'ast> {
+    fn line(&mut self, line: &str) {
+        self.output.push_str(&"    ".repeat(self.depth));
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn name(&self, id: usize) -> String {
+        format!("{}#{id}", self.names.get(&id).copied().unwrap_or(""))
+    }
+}
+
+impl<'ast> Visitor<'ast> for Printer<'ast> {
+    fn visit_declaration(&mut self, declaration: &'ast Declaration) {
+        self.names.insert(declaration.id, &declaration.name);
+        let name = self.name(declaration.id);
+        let line = match declaration.object {
+            Object::Char { health, attack } => format!("char {name} = ({health}, {attack})"),
+            Object::Zombie { health, attack } => format!("zombie {name} = ({health}, {attack})"),
+            Object::Merchant => format!("merchant {name} = ()"),
+            Object::Potion { value } => format!("potion {name} = ({value})"),
+            Object::SpellBook => format!("spellbook {name} = ()")
+        };
+        self.line(&line);
+    }
+
+    fn visit_action(&mut self, action: &'ast Action) {
+        let line = match action.kind {
+            ActionKind::Buys { user, item, merchant } => format!("{} buys {} from {}", self.name(user), self.name(item), self.name(merchant)),
+            ActionKind::Attacks { attacker, attacked } => format!("{} attacks {}", self.name(attacker), self.name(attacked)),
+            ActionKind::Uses { user, potion } => format!("{} uses {}", self.name(user), self.name(potion)),
+            ActionKind::Shouts { user } => format!("{} shouts", self.name(user)),
+            ActionKind::Whispers { user } => format!("{} whispers", self.name(user)),
+            ActionKind::ShoutsSpeak { user, spell_book } => format!("{} shouts {} casting speak()", self.name(user), self.name(spell_book)),
+            ActionKind::WhispersSpeak { user, spell_book } => format!("{} whispers {} casting speak()", self.name(user), self.name(spell_book))
+        };
+        self.line(&line);
+    }
+
+    fn visit_spell(&mut self, spell: &'ast Spell) {
+        let cast = match &spell.kind {
+            SpellKind::UnZombify { zombie } => format!("un_zombify({})", self.name(*zombie)),
+            SpellKind::Confuse { actor } => format!("confuse({})", self.name(*actor)),
+            SpellKind::GodSpeech => "god_speech()".to_string(),
+            SpellKind::TimeWarp { consumed, .. } => format!("time_warp({})", self.name(*consumed)),
+            SpellKind::Shift => "shift()".to_string(),
+            SpellKind::CreatePotion { potion } => format!("create_potion({})", self.name(*potion))
+        };
+        self.line(&format!("{} uses {} casting {cast}", self.name(spell.user), self.name(spell.spell_book)));
+        if let SpellKind::TimeWarp { .. } = spell.kind {
+            self.depth += 1;
+            visit::walk_spell(self, spell);
+            self.depth -= 1;
+            self.line("end");
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Walks the syntax tree without changing it, e.g. for lints and metrics.
+//!
+//! Override the methods for the statements you are interested in. To keep walking into a time
+//! warp body from an overridden method, call the matching `walk_*` function.
+
+use crate::ast::{Action, Declaration, Spell, SpellKind, Stmt};
+
+pub trait Visitor<'ast> {
+    /// Visits a list of statements, either the whole program or the body of a time warp
+    fn visit_stmts(&mut self, stmts: &'ast [Stmt]) {
+        walk_stmts(self, stmts)
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_declaration(&mut self, _declaration: &'ast Declaration) {}
+
+    fn visit_action(&mut self, _action: &'ast Action) {}
+
+    /// Visits the spell and then the body of a time warp
+    fn visit_spell(&mut self, spell: &'ast Spell) {
+        walk_spell(self, spell)
+    }
+}
+
+pub fn walk_stmts<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, stmts: &'ast [Stmt]) {
+    for stmt in stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, stmt: &'ast Stmt) {
+    match stmt {
+        Stmt::Declaration(declaration) => visitor.visit_declaration(declaration),
+        Stmt::Action(action) => visitor.visit_action(action),
+        Stmt::Spell(spell) => visitor.visit_spell(spell)
+    }
+}
+
+pub fn walk_spell<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, spell: &'ast Spell) {
+    if let SpellKind::TimeWarp { body, .. } = &spell.kind {
+        visitor.visit_stmts(body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    /// Declares `a`, which shouts once outside and three times inside nested time warps
+    const PROGRAM: &str = "char a = (1, 1)\nchar b = (1, 1)\nspellbook sb = ()\na shouts\n\
+    a uses sb casting time_warp(b)\n\ta shouts\n\ta uses sb casting time_warp(b)\n\t\ta shouts\n\t\ta shouts\n\tend\nend";
+
+    #[derive(Default)]
+    struct Counter {
+        declarations: usize,
+        actions: usize,
+        spells: usize
+    }
+
+    impl<'ast> Visitor<'ast> for Counter {
+        fn visit_declaration(&mut self, _declaration: &'ast Declaration) {
+            self.declarations += 1;
+        }
+
+        fn visit_action(&mut self, _action: &'ast Action) {
+            self.actions += 1;
+        }
+
+        fn visit_spell(&mut self, spell: &'ast Spell) {
+            self.spells += 1;
+            walk_spell(self, spell);
+        }
+    }
+
+    #[test]
+    fn walks_into_nested_time_warps() {
+        let mut counter = Counter::default();
+        counter.visit_stmts(&parse(PROGRAM));
+        assert_eq!((counter.declarations, counter.actions, counter.spells), (3, 4, 2));
+    }
+
+    /// Only counts the top level statements
+    struct TopLevel(usize);
+
+    impl<'ast> Visitor<'ast> for TopLevel {
+        fn visit_action(&mut self, _action: &'ast Action) {
+            self.0 += 1;
+        }
+
+        fn visit_spell(&mut self, _spell: &'ast Spell) {}
+    }
+
+    #[test]
+    fn overridden_spell_skips_the_body() {
+        let mut top_level = TopLevel(0);
+        top_level.visit_stmts(&parse(PROGRAM));
+        assert_eq!(top_level.0, 1);
+    }
+}
@@ -1,116 +1,409 @@
-use std::{fs, thread};
-use std::cmp::max;
+use std::{fs, io, thread};
 use std::sync::Arc;
-use std::time::Duration;
-use simple_colors::Color;
-use spinner::{SpinnerBuilder, SpinnerHandle};
-use spinners::utils::spinner_data::SpinnerData;
-use crate::{Parser, rm_comments, Tokenizer};
+use std::time::Instant;
+use crate::{Parser, Tokenizer};
+use crate::ast::Stmt;
 use crate::generator::Generator;
+use crate::interpreter::Interpreter;
+use crate::health::HealthChecker;
+use crate::inventory::InventoryChecker;
 use crate::type_checker::TypeChecker;
-use crate::user_output::CompileError;
-
-pub fn compile(file: &str) -> String {
-    let sp = ColoredSpinner::new("Reading input...".to_string());
-    let code = fs::read_to_string(file).expect_compile_error(&format!("{file} could not be found."));
-    let code = rm_comments(&code);
-    let code = code.trim();
-    sp.stop(); println!();
-    let sp = ColoredSpinner::new("Tokenizing...".to_string());
-    let mut tokens = Tokenizer::new(&code).tokenize();
-    sp.stop(); println!();
-    let sp = ColoredSpinner::new("Parsing...".to_string());
-    let parsed = Parser::new(&mut tokens).parse();
-    let parsed = Arc::new(parsed);
-    sp.stop(); println!();
-    let thread_parsed = parsed.clone();
-    let type_checker = thread::spawn(move || {
-        TypeChecker::new(&thread_parsed).check_types();
-    });
-    let sp = ColoredSpinner::new("Generating...".to_string());
-    let generated = Generator::new(&parsed).generate();
-    sp.stop(); println!();
-    type_checker.join().expect("Unable to join type-checker thread.");
-    generated
+use crate::user_output::{CompileError, Diagnostic, ErrorKind};
+
+mod progress;
+pub use progress::*;
+mod pass;
+pub use pass::*;
+
+/// The result of a successful compilation
+pub struct Compiled {
+    /// The generated rust code
+    pub code: String,
+    /// Things that might go wrong when running the program
+    pub warnings: Vec<Diagnostic>
 }
 
+pub fn compile(file: &str) -> Result<Compiled, Vec<Diagnostic>> {
+    compile_with_config(file, Config::default())
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Config {
+    /// The maximum amount of characters allowed in the program
     pub max_char: usize,
-    pub verbose: bool
-}
-
-pub unsafe fn compile_with_config(file: &str, conf: Config) -> String {
-    let sp = ColoredSpinner::new("Reading input...".to_string());
-    let max_char = conf.max_char;
-    let verbose = conf.verbose;
-    if max_char > 10 { println!("Cheater :(") }
-    crate::generator::MAX_CHAR = max_char;
-    crate::user_output::VERBOSE = verbose;
-    let code = fs::read_to_string(file).expect_compile_error(&format!("{file} could not be found."));
-    let code = rm_comments(&code);
-    let code = code.trim();
-    sp.stop(); println!();
-    let sp = ColoredSpinner::new("Tokenizing...".to_string());
-    let mut tokens = Tokenizer::new(&code).tokenize();
-    sp.stop(); println!();
-    let sp = ColoredSpinner::new("Parsing...".to_string());
-    let parsed = Parser::new(&mut tokens).parse();
+    /// Keep the underlying errors of diagnostics as their cause
+    pub verbose: bool,
+    /// Run the part of the program that doesn't read input at compile time, the generated code
+    /// only prints its output. Turn this off to debug the generated code.
+    pub fold: bool
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { max_char: 10, verbose: false, fold: true }
+    }
+}
+
+/// Compiles the file with the given configuration
+pub fn compile_with_config(file: &str, conf: Config) -> Result<Compiled, Vec<Diagnostic>> {
+    compile_with_progress(file, conf, &mut Silent)
+}
+
+/// Compiles the file with the given configuration, reporting the start and end of every phase
+/// to `progress`
+pub fn compile_with_progress(file: &str, conf: Config, progress: &mut dyn Progress) -> Result<Compiled, Vec<Diagnostic>> {
+    compile_with_passes(file, conf, progress, &mut [])
+}
+
+/// Compiles the file, running the custom `passes` on the program before it is checked
+pub fn compile_with_passes(file: &str, conf: Config, progress: &mut dyn Progress, passes: &mut [&mut dyn Pass]) -> Result<Compiled, Vec<Diagnostic>> {
+    let compiled = run_phase(progress, Phase::Reading, || read_source(file))
+        .map_err(|e| vec![e])
+        .and_then(|code| compile_code(file, &code, conf, progress, passes));
+    strip_compiled_causes(compiled, conf)
+}
+
+/// Compiles source code that is not read from a file. `name` is only used to point to the
+/// source code in diagnostics and runtime errors.
+pub fn compile_str(source: &str, name: &str, conf: Config) -> Result<Compiled, Vec<Diagnostic>> {
+    compile_str_with_progress(source, name, conf, &mut Silent)
+}
+
+/// [`compile_str`], reporting the start and end of every phase to `progress`
+pub fn compile_str_with_progress(source: &str, name: &str, conf: Config, progress: &mut dyn Progress) -> Result<Compiled, Vec<Diagnostic>> {
+    compile_str_with_passes(source, name, conf, progress, &mut [])
+}
+
+/// [`compile_str`], running the custom `passes` on the program before it is checked
+pub fn compile_str_with_passes(source: &str, name: &str, conf: Config, progress: &mut dyn Progress, passes: &mut [&mut dyn Pass]) -> Result<Compiled, Vec<Diagnostic>> {
+    strip_compiled_causes(compile_code(name, source, conf, progress, passes), conf)
+}
+
+/// Compiles the code, `name` is the name of the source file
+fn compile_code(name: &str, code: &str, config: Config, progress: &mut dyn Progress, passes: &mut [&mut dyn Pass]) -> Result<Compiled, Vec<Diagnostic>> {
+    let mut tokens = run_phase(progress, Phase::Tokenizing, || Tokenizer::new(code).tokenize()).map_err(|e| vec![e])?;
+    progress.tokens(&tokens);
+    let mut parser = Parser::new(&mut tokens);
+    let mut parsed = run_phase(progress, Phase::Parsing, || parser.parse())?;
+    let mut early_warnings = parser.warnings().to_vec();
+    if !passes.is_empty() {
+        early_warnings.extend(run_phase(progress, Phase::Passes, || run_passes(passes, &mut parsed))?);
+    }
+    progress.program(&parsed);
     let parsed = Arc::new(parsed);
-    sp.stop(); println!();
-    // if let Some(handler) = &handler { &handler.stop_parsing; }
-    // TODO: pb for type checker
     let thread_parsed = parsed.clone();
+    progress.start(Phase::TypeChecking);
     let type_checker = thread::spawn(move || {
-        TypeChecker::new(&thread_parsed).check_types();
+        let start = Instant::now();
+        let result = TypeChecker::new(&thread_parsed).check_types()
+            .and_then(|mut warnings| {
+                warnings.extend(check_actors(&thread_parsed)?);
+                Ok(warnings)
+            });
+        (result, start.elapsed())
     });
-    let sp = ColoredSpinner::new("Generating...".to_string());
-    let generated = Generator::new(&parsed).generate();
-    sp.stop(); println!();
-    // if let Some(handler) = &handler { &handler.stop_generating; }
-    type_checker.join().expect("Unable to join type-checker thread.");
-    generated
-}
-
-struct ColoredSpinner {
-    handle: SpinnerHandle,
-}
-
-impl ColoredSpinner {
-    /// Create a new spinner along with a message
-    ///
-    /// Returns a spinner
-    fn new(message: String) -> Self {
-        // Dots3
-        let spinner_data = SpinnerData {frames: vec![
-            "\x1b[34m⠋\x1b[0m",
-            "\x1b[34m⠙\x1b[0m",
-            "\x1b[34m⠚\x1b[0m",
-            "\x1b[34m⠞\x1b[0m",
-            "\x1b[34m⠖\x1b[0m",
-            "\x1b[34m⠦\x1b[0m",
-            "\x1b[34m⠴\x1b[0m",
-            "\x1b[34m⠲\x1b[0m",
-            "\x1b[34m⠳\x1b[0m",
-            "\x1b[34m⠓\x1b[0m"
-        ], interval: 80};
-        
-        let handle = SpinnerBuilder::new(message)
-            .spinner(spinner_data.frames.clone())
-            .step(Duration::from_millis(spinner_data.interval.into()))
-            .start();
-        
-        ColoredSpinner { handle }
-    }
-    
-    /// Update spinner's message
-    ///
-    /// Returns the String that is put in in case the sender could not send.
-    pub fn message(&self, message: String) -> Option<String> {
-        self.handle.update(message)
-    }
-    
-    /// Stop the spinner
-    pub fn stop(self) {
-        self.handle.close();
-    }
-}
\ No newline at end of file
+    let generated = run_phase(progress, Phase::Generating, || {
+        Generator::new(&parsed, config).with_source(name, code).generate()
+    });
+    let (type_checked, duration) = type_checker.join().expect("Unable to join type-checker thread.");
+    progress.finish(Phase::TypeChecking, duration);
+    type_checked.map(|warnings| Compiled { code: generated, warnings: early_warnings.into_iter().chain(warnings).collect() })
+}
+
+/// Runs the passes in order, stopping after the first pass that reports an error
+fn run_passes(passes: &mut [&mut dyn Pass], program: &mut Vec<Stmt>) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let mut warnings = Vec::new();
+    for pass in passes {
+        warnings.extend(pass.run(program));
+        warnings = Diagnostic::ok_if_warnings(warnings)?;
+    }
+    Ok(warnings)
+}
+
+/// Follows the actors through the type checked program, reporting the diagnostics of all checks
+/// at once
+fn check_actors(program: &[Stmt]) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let mut diagnostics: Vec<Diagnostic> = [
+        InventoryChecker::new(program).check_inventories(),
+        HealthChecker::new(program).check_health()
+    ].into_iter().flat_map(|result| result.unwrap_or_else(|e| e)).collect();
+    diagnostics.sort_by_key(|d| d.span.map(|span| span.start));
+    Diagnostic::ok_if_warnings(diagnostics)
+}
+
+fn run_phase<T>(progress: &mut dyn Progress, phase: Phase, f: impl FnOnce() -> T) -> T {
+    progress.start(phase);
+    let start = Instant::now();
+    let result = f();
+    progress.finish(phase, start.elapsed());
+    result
+}
+
+/// Runs the file directly using the interpreter, reading from stdin and writing to stdout.
+/// `on_warning` is called for every warning before the program starts.
+///
+/// No rust code is generated, so no rust compiler is needed.
+pub fn interpret(file: &str, config: Config, on_warning: impl FnMut(Diagnostic)) -> Result<(), Vec<Diagnostic>> {
+    let code = read_source(file).map_err(|e| strip_causes(vec![e], config))?;
+    interpret_str(&code, config, on_warning)
+}
+
+/// [`interpret`] for source code that is not read from a file
+pub fn interpret_str(source: &str, config: Config, mut on_warning: impl FnMut(Diagnostic)) -> Result<(), Vec<Diagnostic>> {
+    interpret_code(source, config, |warning| on_warning(strip_cause(warning, config)))
+        .map_err(|e| strip_causes(e, config))
+}
+
+fn interpret_code(code: &str, config: Config, on_warning: impl FnMut(Diagnostic)) -> Result<(), Vec<Diagnostic>> {
+    let mut tokens = Tokenizer::new(code).tokenize().map_err(|e| vec![e])?;
+    let mut parser = Parser::new(&mut tokens);
+    let parsed = parser.parse()?;
+    let parser_warnings = parser.warnings().to_vec();
+    let type_warnings = TypeChecker::new(&parsed).check_types()?;
+    parser_warnings.into_iter()
+        .chain(type_warnings)
+        .chain(check_actors(&parsed)?)
+        .for_each(on_warning);
+    let stdin = io::stdin();
+    Interpreter::new(&parsed, config.max_char, stdin.lock(), io::stdout()).run().map_err(|e| vec![e])
+}
+
+/// Removes the underlying error from the diagnostic, unless compiling in verbose mode
+fn strip_cause(mut diagnostic: Diagnostic, config: Config) -> Diagnostic {
+    if !config.verbose {
+        diagnostic.cause = None;
+    }
+    diagnostic
+}
+
+fn strip_compiled_causes(compiled: Result<Compiled, Vec<Diagnostic>>, config: Config) -> Result<Compiled, Vec<Diagnostic>> {
+    match compiled {
+        Ok(compiled) => Ok(Compiled { warnings: strip_causes(compiled.warnings, config), ..compiled }),
+        Err(e) => Err(strip_causes(e, config))
+    }
+}
+
+fn strip_causes(diagnostics: Vec<Diagnostic>, config: Config) -> Vec<Diagnostic> {
+    diagnostics.into_iter().map(|d| strip_cause(d, config)).collect()
+}
+
+/// Reads the source file
+fn read_source(file: &str) -> Result<String, Diagnostic> {
+    fs::read_to_string(file).or_compile_error(Diagnostic::new(
+        ErrorKind::Io,
+        format!("{file} could not be found.")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::Token;
+    use crate::ast::{Action, ActionKind};
+    use crate::test_util::{parse, span, summary};
+    use crate::user_output::Severity;
+
+    const SOURCE: &str = "char a = (3, 1)
+zombie z = (5, 2)
+spellbook sb = ()
+potion p = (4)
+merchant m = ()
+a buys sb from m
+a buys p from m
+a uses p
+a uses sb casting time_warp(a)
+    z attacks a
+    z shouts
+end";
+
+    /// The code generated for the source
+    fn generate(source: &str, config: Config) -> String {
+        compile_str(source, "test.rpg", config).unwrap().code
+    }
+
+    #[test]
+    fn compiling_twice_gives_the_same_code() {
+        assert_eq!(generate(SOURCE, Config::default()), generate(SOURCE, Config::default()));
+    }
+
+    #[test]
+    fn concurrent_compilations_give_the_same_code_as_one_compilation() {
+        let expected = generate(SOURCE, Config::default());
+        let compilations: Vec<_> = (0..8).map(|_| thread::spawn(move || generate(SOURCE, Config::default()))).collect();
+        for compilation in compilations {
+            assert_eq!(compilation.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn concurrent_compilations_keep_their_own_max_char() {
+        let compilations: Vec<_> = [2, 5].into_iter().cycle().take(8).map(|max_char| {
+            let config = Config { max_char, ..Config::default() };
+            (max_char, thread::spawn(move || generate(SOURCE, config)))
+        }).collect();
+        for (max_char, compilation) in compilations {
+            // The generated game stops the program when it has more living actors than this
+            assert!(compilation.join().unwrap().contains(&format!("Game::new({max_char})")), "{max_char}");
+        }
+    }
+
+    #[test]
+    fn long_time_warps_are_not_run_at_compile_time() {
+        // Every iteration of the empty body counts towards the steps run at compile time
+        let source = [
+            "char counter = (4000000000, 0)",
+            "char a = (1, 0)",
+            "spellbook sb = ()",
+            "merchant m = ()",
+            "a buys sb from m",
+            "a uses sb casting time_warp(counter)",
+            "end",
+            "a uses sb casting god_speech()",
+            "a shouts"
+        ].join("\n");
+        let code = generate(&source, Config::default());
+        assert!(code.contains("while game.loop_times("), "{code}");
+    }
+
+    /// Writes the source to a file in the temp dir, the tests remove it when they're done
+    fn source_file(name: &str) -> String {
+        let file = std::env::temp_dir().join(format!("rpg-{}-{name}.rpg", std::process::id()));
+        fs::write(&file, SOURCE).unwrap();
+        file.to_str().unwrap().to_string()
+    }
+
+    /// Records the phases in the order they are reported
+    #[derive(Default)]
+    struct Recorder(Vec<(&'static str, Phase)>);
+
+    impl Progress for Recorder {
+        fn start(&mut self, phase: Phase) {
+            self.0.push(("start", phase));
+        }
+
+        fn finish(&mut self, phase: Phase, _duration: Duration) {
+            self.0.push(("finish", phase));
+        }
+    }
+
+    #[test]
+    fn phases_are_reported_in_order() {
+        let file = source_file("phases");
+        let mut recorder = Recorder::default();
+        let compiled = compile_with_progress(&file, Config::default(), &mut recorder);
+        fs::remove_file(file).unwrap();
+        assert!(compiled.is_ok());
+        assert_eq!(recorder.0, [
+            ("start", Phase::Reading),
+            ("finish", Phase::Reading),
+            ("start", Phase::Tokenizing),
+            ("finish", Phase::Tokenizing),
+            ("start", Phase::Parsing),
+            ("finish", Phase::Parsing),
+            ("start", Phase::TypeChecking),
+            ("start", Phase::Generating),
+            ("finish", Phase::Generating),
+            // Type checking runs at the same time as generating
+            ("finish", Phase::TypeChecking)
+        ]);
+    }
+
+    /// Keeps the tokens and the program it receives
+    #[derive(Default)]
+    struct Inspector {
+        tokens: Vec<Token>,
+        program: Option<Vec<Stmt>>
+    }
+
+    impl Progress for Inspector {
+        fn tokens(&mut self, tokens: &[Token]) {
+            self.tokens = tokens.to_vec();
+        }
+
+        fn program(&mut self, program: &[Stmt]) {
+            self.program = Some(program.to_vec());
+        }
+    }
+
+    #[test]
+    fn tokens_and_program_are_received_when_checking_fails() {
+        let source = ["char a = (1, 1)", "merchant m = ()", "m attacks a"].join("\n");
+        let mut inspector = Inspector::default();
+        let compiled = compile_str_with_progress(&source, "test.rpg", Config::default(), &mut inspector);
+        assert_eq!(compiled.err().map(|diagnostics| diagnostics[0].kind), Some(ErrorKind::Type));
+        assert_eq!(inspector.tokens.len(), Tokenizer::new(&source).tokenize().unwrap().len());
+        assert_eq!(inspector.program, Some(parse(&source)));
+    }
+
+    #[test]
+    fn nothing_is_received_after_a_failed_phase() {
+        let mut inspector = Inspector::default();
+        let compiled = compile_str_with_progress("char a = (1, 1", "test.rpg", Config::default(), &mut inspector);
+        assert!(compiled.is_err());
+        let tokens: Vec<&str> = inspector.tokens.iter().map(|token| token.value.as_str()).collect();
+        assert_eq!(tokens, ["char", "a", "=", "(", "1", ",", "1"]);
+        assert_eq!(inspector.program, None);
+    }
+
+    #[test]
+    fn silent_compilations_print_nothing() {
+        const NAME: &str = "compile::tests::silent_compilations_print_nothing";
+        // Runs the compilation in another process, so its output can be captured
+        if std::env::var_os("RPG_SILENT_CHILD").is_some() {
+            println!("<compiling>");
+            compile_str(SOURCE, "test.rpg", Config::default()).unwrap();
+            println!("</compiling>");
+            return;
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([NAME, "--exact", "--nocapture", "--test-threads=1"])
+            .env("RPG_SILENT_CHILD", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("<compiling>\n</compiling>\n"));
+    }
+
+    #[test]
+    fn passes_run_in_order() {
+        let mut program = parse(SOURCE);
+        let mut warn = |_: &mut Vec<Stmt>| vec![Diagnostic::warning(ErrorKind::Type, "first")];
+        let mut repeat = |program: &mut Vec<Stmt>| {
+            program.push(program[0].clone());
+            vec![Diagnostic::warning(ErrorKind::Type, "second")]
+        };
+        let warnings = run_passes(&mut [&mut warn, &mut repeat], &mut program).unwrap();
+        let messages: Vec<&str> = warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(messages, ["first", "second"]);
+        assert_eq!(program.last(), program.first());
+    }
+
+    #[test]
+    fn an_error_stops_the_passes() {
+        let mut program = parse(SOURCE);
+        let statements = program.len();
+        let mut fail = |_: &mut Vec<Stmt>| vec![Diagnostic::new(ErrorKind::Type, "error")];
+        let mut clear = |program: &mut Vec<Stmt>| {
+            program.clear();
+            vec![]
+        };
+        let errors = run_passes(&mut [&mut fail, &mut clear], &mut program).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(program.len(), statements);
+    }
+
+    #[test]
+    fn rewritten_programs_are_checked() {
+        // Makes `a` use itself as a potion
+        let mut rewrite = |program: &mut Vec<Stmt>| {
+            let span = program[0].span();
+            program.push(Stmt::Action(Action { kind: ActionKind::Uses { user: 1, potion: 1 }, span }));
+            vec![]
+        };
+        let errors = compile_str_with_passes(SOURCE, "test.rpg", Config::default(), &mut Silent, &mut [&mut rewrite]).err().unwrap();
+        assert_eq!(errors.iter().map(summary).collect::<Vec<_>>(), [
+            (ErrorKind::Type, Severity::Error, "The item being used is not a potion.", Some(span(SOURCE, "char a = (3, 1)")))
+        ]);
+    }
+}
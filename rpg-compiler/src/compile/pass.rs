@@ -0,0 +1,19 @@
+use crate::ast::Stmt;
+use crate::user_output::Diagnostic;
+
+/// A custom pass over the program, like a lint or a rewrite, see [`Visitor`](crate::ast::Visitor)
+/// and [`Fold`](crate::ast::Fold).
+///
+/// Passes run in order after the program is parsed and before it is checked, so rewrites are
+/// checked like the rest of the program.
+pub trait Pass {
+    /// Inspects or rewrites the program. The returned diagnostics are reported along with the
+    /// ones of the compiler, an error stops the compilation.
+    fn run(&mut self, program: &mut Vec<Stmt>) -> Vec<Diagnostic>;
+}
+
+impl<F: FnMut(&mut Vec<Stmt>) -> Vec<Diagnostic>> Pass for F {
+    fn run(&mut self, program: &mut Vec<Stmt>) -> Vec<Diagnostic> {
+        self(program)
+    }
+}
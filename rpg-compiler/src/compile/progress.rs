@@ -0,0 +1,36 @@
+use std::time::Duration;
+use crate::Token;
+use crate::ast::Stmt;
+
+/// A phase of the compilation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Reading,
+    Tokenizing,
+    Parsing,
+    /// Runs the custom passes, only when there are any
+    Passes,
+    /// Checks the types and inventories, runs at the same time as `Generating`
+    TypeChecking,
+    Generating
+}
+
+/// Receives the progress of a compilation, for example to show a spinner.
+///
+/// The library itself never writes to stdout.
+pub trait Progress {
+    /// Called when a phase starts
+    fn start(&mut self, _phase: Phase) {}
+    /// Called when a phase ends, along with the time it took
+    fn finish(&mut self, _phase: Phase, _duration: Duration) {}
+    /// Called with the tokens of the source code, without comments, as soon as it is tokenized
+    fn tokens(&mut self, _tokens: &[Token]) {}
+    /// Called with the program after the custom passes, before it is checked. Checking it can
+    /// still fail.
+    fn program(&mut self, _program: &[Stmt]) {}
+}
+
+/// Doesn't report any progress
+pub struct Silent;
+
+impl Progress for Silent {}
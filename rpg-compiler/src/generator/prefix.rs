@@ -0,0 +1,78 @@
+//! Runs the start of a program at compile time.
+//!
+//! Everything before the first statement that reads input always does the same thing, so it is
+//! run by the interpreter. Only its output and the state of the game it leaves behind are
+//! generated.
+
+use std::io;
+use crate::ast::{Spell, SpellKind, Stmt};
+use crate::ast::visit::{self, Visitor};
+use crate::generator::Generator;
+use rpg_runtime::{Actor, ActorHealth, Item};
+use crate::interpreter::Interpreter;
+
+/// The amount of statements and time warp iterations run at compile time, a time warp can run
+/// for a very long time
+const MAX_STEPS: usize = 1_000_000;
+
+impl<'a> Generator<'a> {
+    /// Runs the statements before the first `god_speech()`, returns the code that has the same
+    /// effect and the statements that still have to be generated. Returns `None` when the
+    /// statements can't be run at compile time, e.g. because of a runtime error.
+    pub(super) fn generate_prefix(&self) -> Option<(String, &'a [Stmt])> {
+        let end = self.statements.iter().position(reads_input).unwrap_or(self.statements.len());
+        if end == 0 {
+            return None;
+        }
+        let (prefix, rest) = self.statements.split_at(end);
+        let mut interpreter = Interpreter::new(prefix, self.max_chars, io::empty(), Vec::new())
+            .with_max_steps(MAX_STEPS);
+        interpreter.run().ok()?;
+        let game = interpreter.game();
+        let mut actors = game.actors.iter().collect::<Vec<_>>();
+        actors.sort_by_key(|(id, _)| **id);
+        let mut potions = game.potions.iter().collect::<Vec<_>>();
+        potions.sort_by_key(|(id, _)| **id);
+        let objects = actors.into_iter().map(|(id, actor)| generate_actor(*id, actor))
+            .chain(potions.into_iter().map(|(id, value)| format!("game.add_potion({id}, {value});")))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let alive = game.alive.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(", ");
+        let output = String::from_utf8(interpreter.into_output()).ok()?;
+        let print = if output.is_empty() { String::new() } else { format!("game.write({:?})?;\n", output) };
+        Some((format!("{print}{objects}\ngame.alive = vec![{alive}];\n"), rest))
+    }
+}
+
+/// Inserts the actor in the state the prefix left it in, without adding it to the living actors
+fn generate_actor(id: usize, actor: &Actor) -> String {
+    let health = match actor.health {
+        ActorHealth::Char(health) => format!("ActorHealth::Char({health})"),
+        ActorHealth::Zombie(health) => format!("ActorHealth::Zombie({health})")
+    };
+    let items = actor.items.iter().map(|item| match item {
+        Item::Potion(potion, value) => format!("Item::Potion({potion}, {value})"),
+        Item::SpellBook => "Item::SpellBook".to_string()
+    }).collect::<Vec<String>>().join(", ");
+    format!(
+        "game.actors.insert({id}, Actor {{ health: {health}, attack: {}, items: vec![{items}], confused: {} }});",
+        actor.attack,
+        actor.confused
+    )
+}
+
+/// Whether the statement casts `god_speech()`, also in the body of a time warp
+fn reads_input(statement: &Stmt) -> bool {
+    struct ReadsInput(bool);
+
+    impl<'ast> Visitor<'ast> for ReadsInput {
+        fn visit_spell(&mut self, spell: &'ast Spell) {
+            self.0 |= spell.kind == SpellKind::GodSpeech;
+            visit::walk_spell(self, spell);
+        }
+    }
+
+    let mut visitor = ReadsInput(false);
+    visitor.visit_stmt(statement);
+    visitor.0
+}
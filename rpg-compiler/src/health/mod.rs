@@ -0,0 +1,603 @@
+//! Finds actors that are used after they died, before the program is run.
+//!
+//! The checker follows every path through the program like the inventory checker, keeping the
+//! health and attack of every actor as long as they are known constants. An actor that is dead
+//! on every path to a statement is an error, an actor that is dead on some paths is a warning.
+
+use std::collections::HashMap;
+use crate::ast::{ActionKind, Object, SpellKind, Stmt};
+use crate::user_output::{Diagnostic, ErrorKind, Span};
+
+/// A number that is known at compile time, `Unknown` when it depends on the input or on the path
+/// that was taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Known(i64),
+    Unknown
+}
+
+impl Value {
+    fn join(self, other: Value) -> Value {
+        if self == other { self } else { Value::Unknown }
+    }
+
+    /// Combines two known values, the result is unknown when it doesn't fit in `T`, the program
+    /// would have stopped with an overflow in that case
+    fn zip<T: TryFrom<i64>>(self, other: Value, f: impl FnOnce(i64, i64) -> i64) -> Value {
+        match (self, other) {
+            (Value::Known(a), Value::Known(b)) => {
+                let result = f(a, b);
+                if T::try_from(result).is_ok() { Value::Known(result) } else { Value::Unknown }
+            }
+            _ => Value::Unknown
+        }
+    }
+}
+
+/// The health of an actor, the kind is unknown when an `un_zombify` may or may not have turned
+/// a zombie into a character
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Health {
+    Char(Value),
+    Zombie(Value),
+    Unknown
+}
+
+impl Health {
+    fn join(self, other: Health) -> Health {
+        match (self, other) {
+            (Health::Char(a), Health::Char(b)) => Health::Char(a.join(b)),
+            (Health::Zombie(a), Health::Zombie(b)) => Health::Zombie(a.join(b)),
+            _ => Health::Unknown
+        }
+    }
+
+    /// Whether the actor is a character without health, the runtime treats those as dead
+    fn is_corpse(self) -> bool {
+        self == Health::Char(Value::Known(0))
+    }
+}
+
+/// Whether an actor is in the list of living actors of the runtime
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Liveness {
+    Alive,
+    Dead,
+    /// Alive on some paths, dead on others
+    Maybe
+}
+
+impl Liveness {
+    fn join(self, other: Liveness) -> Liveness {
+        if self == other { self } else { Liveness::Maybe }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Actor {
+    health: Health,
+    attack: Value,
+    liveness: Liveness,
+    /// Where the actor died on one of the paths, pointed to in diagnostics
+    death: Option<Span>
+}
+
+impl Actor {
+    fn new(health: Health, attack: u32) -> Self {
+        Self { health, attack: Value::Known(attack as i64), liveness: Liveness::Alive, death: None }
+    }
+
+    /// An actor the checker knows nothing about, it is assumed to be alive
+    fn unknown() -> Self {
+        Self { health: Health::Unknown, attack: Value::Unknown, liveness: Liveness::Alive, death: None }
+    }
+
+    fn join(self, other: Actor) -> Actor {
+        Actor {
+            health: self.health.join(other.health),
+            attack: self.attack.join(other.attack),
+            liveness: self.liveness.join(other.liveness),
+            death: self.death.or(other.death)
+        }
+    }
+
+    fn die(&mut self, span: Span) {
+        self.liveness = Liveness::Dead;
+        self.death = Some(span);
+    }
+
+    /// The actor died on some paths
+    fn may_die(&mut self, span: Span) {
+        self.liveness = self.liveness.join(Liveness::Dead);
+        self.death = self.death.or(Some(span));
+    }
+}
+
+/// The actors and potions at a point in the program
+#[derive(Debug, Clone, Default, PartialEq)]
+struct State {
+    actors: HashMap<usize, Actor>,
+    /// The healing value of every potion
+    potions: HashMap<usize, Value>
+}
+
+impl State {
+    /// The state at a point that can be reached with either `self` or `other`
+    fn join(&self, other: &State) -> State {
+        let mut joined = self.clone();
+        for (id, actor) in &other.actors {
+            joined.actors.entry(*id).and_modify(|a| *a = a.join(*actor)).or_insert(*actor);
+        }
+        for (id, value) in &other.potions {
+            joined.potions.entry(*id).and_modify(|v| *v = v.join(*value)).or_insert(*value);
+        }
+        joined
+    }
+
+    fn actor(&self, id: usize) -> Actor {
+        self.actors.get(&id).copied().unwrap_or_else(Actor::unknown)
+    }
+
+    fn actor_mut(&mut self, id: usize) -> &mut Actor {
+        self.actors.entry(id).or_insert_with(Actor::unknown)
+    }
+}
+
+/// The amount of time warp iterations followed one by one in a whole program
+const MAX_ITERATIONS: usize = 10_000;
+
+pub struct HealthChecker<'a> {
+    statements: &'a [Stmt],
+    /// The names of all declared actors, used in messages
+    names: HashMap<usize, String>,
+    /// Off while the states of a time warp body are joined, the body is reported once with the
+    /// joined state
+    report: bool,
+    /// The amount of time warp iterations that can still be followed one by one, so large or
+    /// nested time warps don't take forever to check
+    iterations: usize,
+    diagnostics: Vec<Diagnostic>
+}
+
+impl<'a> HealthChecker<'a> {
+    pub fn new(statements: &'a [Stmt]) -> Self {
+        Self {
+            statements,
+            names: HashMap::new(),
+            report: true,
+            iterations: MAX_ITERATIONS,
+            diagnostics: Vec::new()
+        }
+    }
+
+    /// Checks that no dead actors are used. Actors are only known to be zombies or characters in
+    /// a type checked program.
+    pub fn check_health(&mut self) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let mut state = State::default();
+        self.check_all(self.statements, &mut state);
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort_by_key(|d| d.span.map(|span| span.start));
+        Diagnostic::ok_if_warnings(diagnostics)
+    }
+
+    fn check_all(&mut self, statements: &'a [Stmt], state: &mut State) {
+        statements.iter().for_each(|statement| self.check_statement(statement, state))
+    }
+
+    fn check_statement(&mut self, statement: &'a Stmt, state: &mut State) {
+        match statement {
+            Stmt::Declaration(declaration) => match declaration.object {
+                Object::Char { health, attack } => {
+                    self.names.insert(declaration.id, declaration.name.clone());
+                    state.actors.insert(declaration.id, Actor::new(Health::Char(Value::Known(health as i64)), attack));
+                }
+                Object::Zombie { health, attack } => {
+                    self.names.insert(declaration.id, declaration.name.clone());
+                    state.actors.insert(declaration.id, Actor::new(Health::Zombie(Value::Known(health as i64)), attack));
+                }
+                Object::Potion { value } => {
+                    state.potions.insert(declaration.id, Value::Known(value as i64));
+                }
+                Object::Merchant | Object::SpellBook => {}
+            }
+            Stmt::Action(action) => match action.kind {
+                ActionKind::Buys { user, .. } => self.expect_alive(state, user, "buy items", action.span),
+                ActionKind::Attacks { attacker, attacked } => {
+                    self.expect_alive(state, attacker, "attack", action.span);
+                    let attack = state.actor(attacker).attack;
+                    self.attacked(state, attacked, attack, action.span);
+                }
+                ActionKind::Uses { user, potion } => {
+                    let heal = state.potions.get(&potion).copied().unwrap_or(Value::Unknown);
+                    let actor = state.actor(user);
+                    // Dead actors don't use their potions, the program continues
+                    if actor.liveness == Liveness::Dead {
+                        if self.report {
+                            let name = self.name(user);
+                            let mut diagnostic = Diagnostic::warning(ErrorKind::DeadActor, format!("{name} is dead, using a potion does nothing."))
+                                .with_hint(format!("Dead actors keep their potions, make sure {name} has health left to use them."))
+                                .with_span(action.span);
+                            if let Some(death) = actor.death {
+                                diagnostic = diagnostic.with_note(death, format!("{name} dies here"));
+                            }
+                            self.diagnostics.push(diagnostic);
+                        }
+                        return;
+                    }
+                    // Characters without health that didn't die yet can't be healed, that stops the program
+                    let liveness = if actor.health.is_corpse() && actor.liveness == Liveness::Alive { Liveness::Dead } else { actor.liveness };
+                    self.report_dead(user, liveness, actor.death, "use potions", action.span);
+                    let healed = match actor.health {
+                        Health::Char(health) => Health::Char(health.zip::<u32>(heal, |h, heal| h + heal)),
+                        Health::Zombie(health) => Health::Zombie(health.zip::<i32>(heal, |h, heal| h + heal)),
+                        Health::Unknown => Health::Unknown
+                    };
+                    let actor = state.actor_mut(user);
+                    actor.health = if actor.liveness == Liveness::Alive { healed } else { actor.health.join(healed) };
+                }
+                ActionKind::Shouts { user } | ActionKind::ShoutsSpeak { user, .. } => {
+                    self.expect_alive(state, user, "shout", action.span);
+                }
+                ActionKind::Whispers { user } | ActionKind::WhispersSpeak { user, .. } => {
+                    // Whispering only looks at the health of characters
+                    let actor = state.actor(user);
+                    let liveness = match actor.health {
+                        Health::Char(Value::Known(0)) => Liveness::Dead,
+                        Health::Char(Value::Known(_)) | Health::Zombie(_) => Liveness::Alive,
+                        Health::Char(Value::Unknown) | Health::Unknown => actor.liveness.join(Liveness::Alive)
+                    };
+                    self.report_dead(user, liveness, actor.death, "whisper", action.span);
+                }
+            }
+            Stmt::Spell(spell) => {
+                let user = state.actor(spell.user);
+                match &spell.kind {
+                    SpellKind::UnZombify { zombie } => {
+                        let actor = state.actor_mut(*zombie);
+                        match actor.health {
+                            Health::Zombie(Value::Known(health)) if health <= 0 => actor.die(spell.span),
+                            Health::Zombie(Value::Known(health)) => actor.health = Health::Char(Value::Known(health)),
+                            Health::Zombie(Value::Unknown) | Health::Unknown => {
+                                actor.health = Health::Unknown;
+                                actor.may_die(spell.span);
+                            }
+                            Health::Char(_) => {}
+                        }
+                    }
+                    SpellKind::GodSpeech => {
+                        let actor = state.actor_mut(spell.user);
+                        actor.health = match actor.health {
+                            Health::Char(_) => Health::Char(Value::Unknown),
+                            Health::Zombie(_) => Health::Zombie(Value::Unknown),
+                            Health::Unknown => Health::Unknown
+                        };
+                    }
+                    SpellKind::TimeWarp { consumed, body } => self.check_time_warp(*consumed, body, spell.span, state),
+                    SpellKind::Shift => {
+                        let actor = state.actor_mut(spell.user);
+                        match actor.health {
+                            Health::Char(health) => {
+                                actor.health = Health::Char(actor.attack);
+                                actor.attack = health;
+                            }
+                            Health::Unknown => actor.attack = Value::Unknown,
+                            Health::Zombie(_) => {}
+                        }
+                    }
+                    SpellKind::CreatePotion { potion } => {
+                        let value = if let Health::Char(health) = user.health { health } else { Value::Unknown };
+                        state.potions.insert(*potion, value);
+                    }
+                    SpellKind::Confuse { .. } => {}
+                }
+            }
+        }
+    }
+
+    /// The body of a time warp runs until the consumed character has no health left.
+    ///
+    /// As long as the health of the character is known, the time warp is followed iteration by
+    /// iteration. Otherwise the state at the start of the body is the state before the time warp
+    /// joined with the state after every iteration that doesn't consume the last health, once it
+    /// stops changing.
+    fn check_time_warp(&mut self, consumed: usize, body: &'a [Stmt], span: Span, state: &mut State) {
+        if self.unroll_time_warp(consumed, body, span, state) {
+            return;
+        }
+        let before = state.actor(consumed);
+        let entry = state.clone();
+        let report = self.report;
+        self.report = false;
+        loop {
+            let mut iteration = state.clone();
+            self.check_all(body, &mut iteration);
+            let actor = iteration.actor(consumed);
+            let left = match actor.health {
+                Health::Char(Value::Known(health)) if health > 1 => Value::Known(health - 1),
+                // Every iteration consumes the last health, so nothing flows back to the start
+                Health::Char(Value::Known(_)) | Health::Zombie(_) => break,
+                Health::Char(Value::Unknown) | Health::Unknown => Value::Unknown
+            };
+            iteration.actors.insert(consumed, Actor { health: Health::Char(left), ..actor });
+            let joined = state.join(&iteration);
+            if joined == *state {
+                break;
+            }
+            *state = joined;
+        }
+        self.report = report;
+        let mut after = state.clone();
+        self.check_all(body, &mut after);
+        // The last iteration consumes the last health of the character
+        let mut actor = after.actor(consumed);
+        actor.health = Health::Char(Value::Known(0));
+        if actor.liveness != Liveness::Dead {
+            actor.die(span);
+        }
+        after.actors.insert(consumed, actor);
+        if before.health == Health::Char(Value::Unknown) {
+            // The body may not run at all
+            let mut skipped = entry;
+            skipped.actors.insert(consumed, Actor { health: Health::Char(Value::Known(0)), ..before });
+            after = after.join(&skipped);
+        }
+        *state = after;
+    }
+
+    /// Runs the time warp iteration by iteration, returns false when the health of the consumed
+    /// character becomes unknown or the iterations run out. The state is only changed when the
+    /// time warp could be followed until the end.
+    fn unroll_time_warp(&mut self, consumed: usize, body: &'a [Stmt], span: Span, state: &mut State) -> bool {
+        let mut unrolled = state.clone();
+        let reported = self.diagnostics.len();
+        loop {
+            match unrolled.actor(consumed).health {
+                Health::Char(Value::Known(0)) => break,
+                Health::Char(Value::Known(_)) if self.iterations > 0 => self.iterations -= 1,
+                _ => {
+                    self.diagnostics.truncate(reported);
+                    return false;
+                }
+            }
+            self.check_all(body, &mut unrolled);
+            self.attacked(&mut unrolled, consumed, Value::Known(1), span);
+        }
+        // Every iteration reports the same mistakes
+        for diagnostic in self.diagnostics.split_off(reported) {
+            if !self.diagnostics[reported..].contains(&diagnostic) {
+                self.diagnostics.push(diagnostic);
+            }
+        }
+        *state = unrolled;
+        true
+    }
+
+    /// Subtracts `attack` from the health of the actor, characters die when their health reaches 0
+    fn attacked(&mut self, state: &mut State, id: usize, attack: Value, span: Span) {
+        let actor = state.actor(id);
+        if actor.health.is_corpse() {
+            if self.report {
+                let name = self.name(id);
+                let mut diagnostic = Diagnostic::warning(ErrorKind::DeadActor, format!("{name} is already dead. Stop beating a dead corpse."))
+                    .with_hint(format!("Attacking {name} does nothing, characters without health stay dead."))
+                    .with_span(span);
+                if let Some(death) = actor.death {
+                    diagnostic = diagnostic.with_note(death, format!("{name} dies here"));
+                }
+                self.diagnostics.push(diagnostic);
+            }
+            return;
+        }
+        let actor = state.actor_mut(id);
+        match (actor.health, attack) {
+            // An attack of 0 only kills characters that are dead already
+            (_, Value::Known(0)) => {}
+            (Health::Char(Value::Known(health)), Value::Known(attack)) if health <= attack => {
+                actor.health = Health::Char(Value::Known(0));
+                actor.die(span);
+            }
+            (Health::Char(health), _) => {
+                actor.health = Health::Char(health.zip::<u32>(attack, |h, a| h - a));
+                if actor.health == Health::Char(Value::Unknown) {
+                    actor.may_die(span);
+                }
+            }
+            (Health::Zombie(health), _) => actor.health = Health::Zombie(health.zip::<i32>(attack, |h, a| h - a)),
+            (Health::Unknown, _) => actor.may_die(span)
+        }
+    }
+
+    /// Reports when the actor is not in the list of living actors. Afterwards, the actor is
+    /// assumed to be alive, because the program would have stopped otherwise.
+    fn expect_alive(&mut self, state: &mut State, id: usize, verb: &str, span: Span) {
+        let actor = state.actor_mut(id);
+        let (liveness, death) = (actor.liveness, actor.death);
+        actor.liveness = Liveness::Alive;
+        self.report_dead(id, liveness, death, verb, span);
+    }
+
+    fn report_dead(&mut self, id: usize, liveness: Liveness, death: Option<Span>, verb: &str, span: Span) {
+        if liveness == Liveness::Alive || !self.report {
+            return;
+        }
+        let name = self.name(id);
+        let (diagnostic, note) = if liveness == Liveness::Dead {
+            let diagnostic = Diagnostic::new(ErrorKind::DeadActor, format!("{name} is dead and can't {verb}."))
+                .with_hint(format!("Characters die when their health reaches 0, make sure {name} has health left."));
+            (diagnostic, format!("{name} dies here"))
+        } else {
+            let diagnostic = Diagnostic::warning(ErrorKind::DeadActor, format!("{name} may be dead and unable to {verb} here."))
+                .with_hint(format!("{name} dies on some paths to this point, e.g. in a time warp or depending on the input."));
+            (diagnostic, format!("{name} may die here"))
+        };
+        let diagnostic = diagnostic.with_span(span);
+        self.diagnostics.push(match death {
+            Some(death) => diagnostic.with_note(death, note),
+            None => diagnostic
+        });
+    }
+
+    fn name(&self, id: usize) -> String {
+        self.names.get(&id).cloned().unwrap_or_else(|| format!("#{id}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{parse, span, span_at, summary};
+    use crate::user_output::Severity;
+
+    fn check(source: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        HealthChecker::new(&parse(source)).check_health()
+    }
+
+    #[test]
+    fn dead_actor_shouts() {
+        let source = ["char a = (1, 1)", "char b = (1, 1)", "b attacks a", "a shouts"].join("\n");
+        let diagnostics = check(&source).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(summary(&diagnostics[0]), (ErrorKind::DeadActor, Severity::Error, "a is dead and can't shout.", Some(span(&source, "a shouts"))));
+        assert_eq!(diagnostics[0].notes[0].span, span(&source, "b attacks a"));
+    }
+
+    #[test]
+    fn living_actor_shouts() {
+        let source = ["char a = (2, 1)", "char b = (1, 1)", "b attacks a", "a shouts", "a attacks b"].join("\n");
+        assert_eq!(check(&source), Ok(vec![]));
+    }
+
+    #[test]
+    fn redeclared_actor_is_alive() {
+        let source = ["char a = (1, 1)", "char b = (1, 1)", "b attacks a", "char a = (1, 1)", "a shouts"].join("\n");
+        assert_eq!(check(&source).map(|warnings| warnings.len()), Ok(0));
+    }
+
+    #[test]
+    fn actor_may_be_dead_after_an_unknown_attack() {
+        let source = ["char a = (1, 1)", "char b = (1, 1)", "spellbook sb = ()", "a uses sb casting god_speech()", "b attacks a", "a shouts"].join("\n");
+        let warnings = check(&source).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            summary(&warnings[0]),
+            (ErrorKind::DeadActor, Severity::Warning, "a may be dead and unable to shout here.", Some(span(&source, "a shouts")))
+        );
+        assert_eq!(warnings[0].notes[0].span, span(&source, "b attacks a"));
+    }
+
+    #[test]
+    fn zombies_survive_attacks() {
+        let source = ["zombie z = (1, 1)", "char b = (1, 5)", "b attacks z", "z shouts", "b attacks z"].join("\n");
+        assert_eq!(check(&source), Ok(vec![]));
+    }
+
+    #[test]
+    fn whispering_only_needs_health() {
+        let source = ["char a = (1, 1)", "char b = (1, 1)", "b attacks a", "a whispers"].join("\n");
+        let diagnostics = check(&source).unwrap_err();
+        assert_eq!(summary(&diagnostics[0]), (ErrorKind::DeadActor, Severity::Error, "a is dead and can't whisper.", Some(span(&source, "a whispers"))));
+        let source = ["zombie z = (1, 1)", "char b = (1, 1)", "b attacks z", "z whispers"].join("\n");
+        assert_eq!(check(&source), Ok(vec![]));
+    }
+
+    #[test]
+    fn beating_a_dead_corpse() {
+        let source = ["char a = (1, 1)", "char b = (1, 1)", "b attacks a", "b attacks a"].join("\n");
+        let warnings = check(&source).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            summary(&warnings[0]),
+            (ErrorKind::DeadActor, Severity::Warning, "a is already dead. Stop beating a dead corpse.", Some(span_at(&source, "b attacks a", 1)))
+        );
+        assert_eq!(warnings[0].notes[0].span, span(&source, "b attacks a"));
+    }
+
+    #[test]
+    fn attacking_a_weakened_character() {
+        let source = ["char a = (2, 1)", "char b = (1, 1)", "b attacks a", "b attacks a"].join("\n");
+        assert_eq!(check(&source), Ok(vec![]));
+    }
+
+    #[test]
+    fn actor_without_health_uses_a_potion() {
+        let source = ["char a = (0, 1)", "potion p = (1)", "merchant m = ()", "a buys p from m", "a uses p"].join("\n");
+        let diagnostics = check(&source).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(summary(&diagnostics[0]), (ErrorKind::DeadActor, Severity::Error, "a is dead and can't use potions.", Some(span(&source, "a uses p"))));
+    }
+
+    #[test]
+    fn dead_actor_uses_a_potion() {
+        let source = ["char a = (1, 1)", "char b = (1, 1)", "potion p = (1)", "merchant m = ()", "a buys p from m", "b attacks a", "a uses p"].join("\n");
+        let warnings = check(&source).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            summary(&warnings[0]),
+            (ErrorKind::DeadActor, Severity::Warning, "a is dead, using a potion does nothing.", Some(span(&source, "a uses p")))
+        );
+        assert_eq!(warnings[0].notes[0].span, span(&source, "b attacks a"));
+    }
+
+    #[test]
+    fn living_actor_uses_a_potion() {
+        let source = ["char a = (1, 1)", "potion p = (1)", "merchant m = ()", "a buys p from m", "a uses p", "a shouts"].join("\n");
+        assert_eq!(check(&source), Ok(vec![]));
+    }
+
+    /// `a` dies in the second of three iterations, and is attacked and shouts afterwards
+    const TIME_WARP: [&str; 8] = [
+        "char counter = (3, 0)",
+        "char a = (2, 1)",
+        "char b = (5, 1)",
+        "spellbook sb = ()",
+        "b uses sb casting time_warp(counter)",
+        "\tb attacks a",
+        "\ta shouts",
+        "end"
+    ];
+
+    #[test]
+    fn time_warp_is_followed_iteration_by_iteration() {
+        let source = TIME_WARP.join("\n");
+        let diagnostics = check(&source).unwrap_err();
+        // Every iteration after the second reports the same mistakes, they are only reported once
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            summary(&diagnostics[0]),
+            (ErrorKind::DeadActor, Severity::Warning, "a is already dead. Stop beating a dead corpse.", Some(span(&source, "b attacks a")))
+        );
+        assert_eq!(summary(&diagnostics[1]), (ErrorKind::DeadActor, Severity::Error, "a is dead and can't shout.", Some(span(&source, "a shouts"))));
+    }
+
+    #[test]
+    fn time_warp_without_iterations_left_is_joined() {
+        let source = TIME_WARP.join("\n");
+        let program = parse(&source);
+        let mut checker = HealthChecker::new(&program);
+        // The health of `a` depends on the iteration, so it is unknown at the start of the body
+        checker.iterations = 2;
+        let warnings = checker.check_health().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            summary(&warnings[0]),
+            (ErrorKind::DeadActor, Severity::Warning, "a may be dead and unable to shout here.", Some(span(&source, "a shouts")))
+        );
+    }
+
+    #[test]
+    fn time_warp_iterations_are_capped() {
+        // Following every iteration would take forever
+        let source = ["char counter = (4000000000, 0)", "char a = (1, 0)", "spellbook sb = ()", "a uses sb casting time_warp(counter)", "\ta shouts", "end"].join("\n");
+        let program = parse(&source);
+        let mut checker = HealthChecker::new(&program);
+        assert_eq!(checker.check_health(), Ok(vec![]));
+        assert_eq!(checker.iterations, 0);
+    }
+
+    #[test]
+    fn consumed_character_is_dead_after_the_time_warp() {
+        let source = ["char counter = (2, 0)", "char a = (1, 0)", "spellbook sb = ()", "a uses sb casting time_warp(counter)", "end", "counter shouts"].join("\n");
+        let diagnostics = check(&source).unwrap_err();
+        assert_eq!(summary(&diagnostics[0]), (ErrorKind::DeadActor, Severity::Error, "counter is dead and can't shout.", Some(span(&source, "counter shouts"))));
+    }
+}
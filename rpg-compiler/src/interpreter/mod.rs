@@ -0,0 +1,191 @@
+//! Executes a parsed program directly, without generating and compiling rust code.
+//!
+//! The statements are run on the `Game` of `rpg_runtime`, the same runtime compiled programs use.
+
+use std::io::{BufRead, Write};
+use rpg_runtime::{Actor, ActorHealth, Game, RuntimeError};
+use crate::ast::{ActionKind, Declaration, Object, Spell, SpellKind, Stmt};
+use crate::user_output::{Diagnostic, ErrorKind};
+
+/// RPG interpreter
+pub struct Interpreter<'a, R: BufRead, W: Write> {
+    /// The code
+    statements: &'a [Stmt],
+    /// The objects of the running program
+    game: Game<R, W>,
+    /// The amount of statements and time warp iterations that can still be run, `None` = no limit
+    steps_left: Option<usize>
+}
+
+impl<'a, R: BufRead, W: Write> Interpreter<'a, R, W> {
+    pub fn new(statements: &'a [Stmt], max_chars: usize, input: R, output: W) -> Self {
+        Self {
+            statements,
+            game: Game::with_io(max_chars, input, output),
+            steps_left: None
+        }
+    }
+
+    /// Stops the program with an error after `max_steps` statements and time warp iterations, a
+    /// time warp can run for a very long time, even without statements in its body
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.steps_left = Some(max_steps);
+        self
+    }
+
+    /// Runs the program until the end or until the first runtime error
+    pub fn run(&mut self) -> Result<(), Diagnostic> {
+        let result = self.run_all(self.statements);
+        self.game.flush().map_err(runtime_error)?;
+        result
+    }
+
+    /// The objects of the program in the state it left them in
+    pub(crate) fn game(&self) -> &Game<R, W> {
+        &self.game
+    }
+
+    /// Consumes the interpreter, returning everything the program wrote
+    pub fn into_output(self) -> W {
+        self.game.into_output()
+    }
+
+    fn run_all(&mut self, statements: &'a [Stmt]) -> Result<(), Diagnostic> {
+        statements.iter().try_for_each(|statement| {
+            self.step()?;
+            // Errors in a time warp body point to the statement in the body, not the time warp
+            self.run_next(statement).map_err(|e| if e.span.is_none() { e.with_span(statement.span()) } else { e })
+        })
+    }
+
+    /// Uses up one of the steps left
+    fn step(&mut self) -> Result<(), Diagnostic> {
+        if let Some(steps_left) = &mut self.steps_left {
+            *steps_left = steps_left.checked_sub(1)
+                .ok_or_else(|| Diagnostic::new(ErrorKind::Runtime, "The program ran too many statements."))?;
+        }
+        Ok(())
+    }
+
+    fn run_next(&mut self, statement: &'a Stmt) -> Result<(), Diagnostic> {
+        match statement {
+            Stmt::Declaration(declaration) => self.declare(declaration).map_err(runtime_error),
+            Stmt::Action(action) => self.run_action(action.kind).map_err(runtime_error),
+            Stmt::Spell(spell) => self.run_spell(spell)
+        }
+    }
+
+    fn declare(&mut self, declaration: &Declaration) -> Result<(), RuntimeError> {
+        match declaration.object {
+            Object::Char { health, attack } => self.game.add_actor(declaration.id, Actor::new(ActorHealth::Char(health), attack)),
+            Object::Zombie { health, attack } => self.game.add_actor(declaration.id, Actor::new(ActorHealth::Zombie(health), attack)),
+            Object::Potion { value } => {
+                self.game.add_potion(declaration.id, value);
+                Ok(())
+            }
+            // Merchants have an infinite stock and every item that is not a potion is a spellbook
+            Object::Merchant | Object::SpellBook => Ok(())
+        }
+    }
+
+    fn run_action(&mut self, action: ActionKind) -> Result<(), RuntimeError> {
+        match action {
+            ActionKind::Buys { user, item, .. } => self.game.buys(user, item),
+            ActionKind::Attacks { attacker, attacked } => self.game.attacks(attacker, attacked),
+            ActionKind::Uses { user, potion } => self.game.uses(user, potion),
+            ActionKind::Shouts { user } => self.game.shouts(user),
+            ActionKind::ShoutsSpeak { user, .. } => self.game.shouts_speak(user),
+            ActionKind::Whispers { user } => self.game.whispers(user),
+            ActionKind::WhispersSpeak { user, .. } => self.game.whispers_speak(user)
+        }
+    }
+
+    fn run_spell(&mut self, spell: &'a Spell) -> Result<(), Diagnostic> {
+        self.game.expect_spellbook(spell.user).map_err(runtime_error)?;
+        match &spell.kind {
+            SpellKind::UnZombify { zombie } => self.game.un_zombify(*zombie).map_err(runtime_error),
+            SpellKind::Confuse { actor } => self.game.confuse(*actor).map_err(runtime_error),
+            SpellKind::GodSpeech => self.game.god_speech(spell.user).map_err(runtime_error),
+            SpellKind::TimeWarp { consumed, body } => {
+                // NOTE: actors are consumed at the end of an iteration
+                let declared: Vec<usize> = body.iter().filter_map(Stmt::declared_actor).collect();
+                while self.game.loop_times(*consumed).map_err(runtime_error)? != 0 {
+                    self.step()?;
+                    self.run_all(body)?;
+                    self.game.remove_actors(&declared);
+                    self.game.attacked(*consumed, 1).map_err(runtime_error)?;
+                }
+                Ok(())
+            }
+            SpellKind::Shift => self.game.shift(spell.user).map_err(runtime_error),
+            SpellKind::CreatePotion { potion } => self.game.create_potion(spell.user, *potion).map_err(runtime_error)
+        }
+    }
+}
+
+fn runtime_error(error: RuntimeError) -> Diagnostic {
+    let diagnostic = Diagnostic::new(ErrorKind::Runtime, error.message);
+    match error.hint {
+        Some(hint) => diagnostic.with_hint(hint),
+        None => diagnostic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use crate::test_util::parse;
+
+    /// Runs the program with the input, returning its result and everything it wrote
+    fn run(source: &str, input: &str, max_chars: usize) -> (Result<(), Diagnostic>, String) {
+        let program = parse(source);
+        let mut output = Vec::new();
+        let result = Interpreter::new(&program, max_chars, input.as_bytes(), &mut output).run();
+        (result, String::from_utf8(output).expect("The output is utf-8"))
+    }
+
+    #[test]
+    fn reads_the_input_and_writes_the_output() {
+        let source = ["char a = (1, 0)", "spellbook sb = ()", "merchant m = ()", "a buys sb from m", "a uses sb casting god_speech()", "a shouts", "a whispers"].join("\n");
+        let (result, output) = run(&source, "42\n", 10);
+        assert!(result.is_ok());
+        assert_eq!(output, "42\n42");
+    }
+
+    #[test]
+    fn invalid_input() {
+        let source = ["char a = (1, 0)", "spellbook sb = ()", "merchant m = ()", "a buys sb from m", "a uses sb casting god_speech()"].join("\n");
+        let (result, _) = run(&source, "not a number\n", 10);
+        let error = result.unwrap_err();
+        assert_eq!((error.kind, error.message.as_str()), (ErrorKind::Runtime, "Invalid input"));
+    }
+
+    #[test]
+    fn too_many_actors() {
+        let source = ["char a = (1, 0)", "char b = (1, 0)", "a shouts"].join("\n");
+        let (result, output) = run(&source, "", 1);
+        assert!(result.unwrap_err().message.starts_with("Your actors exceeded the maximum amount of actors allowed (1)"));
+        assert_eq!(output, "");
+        let (result, _) = run(&source, "", 2);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn output_before_a_runtime_error_is_written() {
+        let source = ["char a = (1, 0)", "char b = (1, 1)", "a shouts", "b attacks a", "a shouts"].join("\n");
+        let (result, output) = run(&source, "", 10);
+        assert_eq!(result.unwrap_err().message, "Dead actors can't shout.");
+        assert_eq!(output, "1\n");
+    }
+
+    #[test]
+    fn time_warp_iterations_are_steps() {
+        let source = ["char counter = (100, 0)", "char a = (1, 0)", "spellbook sb = ()", "merchant m = ()", "a buys sb from m", "a uses sb casting time_warp(counter)", "end"].join("\n");
+        let program = parse(&source);
+        let mut interpreter = Interpreter::new(&program, 10, io::empty(), Vec::new()).with_max_steps(50);
+        assert_eq!(interpreter.run().unwrap_err().message, "The program ran too many statements.");
+        let mut interpreter = Interpreter::new(&program, 10, io::empty(), Vec::new()).with_max_steps(200);
+        assert!(interpreter.run().is_ok());
+    }
+}
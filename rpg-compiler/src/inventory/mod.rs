@@ -0,0 +1,316 @@
+//! Finds actors using items they don't own, before the program is run.
+//!
+//! The checker follows every path through the program, counting how many of each item an actor
+//! can hold at each statement. An actor that holds none of an item on every path is an error, an
+//! actor that holds none on some paths (e.g. when a time warp body runs 0 times) is a warning.
+
+use std::collections::{HashMap, HashSet};
+use crate::ast::{ActionKind, Object, SpellKind, Stmt};
+use crate::user_output::{Diagnostic, ErrorKind, Span};
+
+/// An item as seen by the inventory of an actor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Held {
+    Potion(usize),
+    /// All spellbooks are the same, any of them can be used to cast a spell
+    SpellBook
+}
+
+/// The amount of an item an actor holds, at least `min` and at most `max` (`None` = no limit)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Count {
+    min: u32,
+    max: Option<u32>
+}
+
+impl Count {
+    const NONE: Count = Count { min: 0, max: Some(0) };
+
+    fn join(self, other: Count) -> Count {
+        Count {
+            min: self.min.min(other.min),
+            max: self.max.zip(other.max).map(|(a, b)| a.max(b))
+        }
+    }
+
+    /// Joins the counts, jumping to the bounds of the lattice when a count keeps changing so
+    /// loops are guaranteed to converge
+    fn widen(self, other: Count) -> Count {
+        let joined = self.join(other);
+        Count {
+            min: if joined.min < self.min { 0 } else { joined.min },
+            max: if joined.max != self.max { None } else { joined.max }
+        }
+    }
+}
+
+/// The counts of the items in all inventories, missing entries are [`Count::NONE`]
+type Inventories = HashMap<(usize, Held), Count>;
+
+pub struct InventoryChecker<'a> {
+    statements: &'a [Stmt],
+    /// The names of all declared objects, used in messages
+    names: HashMap<usize, String>,
+    potions: HashSet<usize>,
+    /// Items an actor buys somewhere in the program
+    bought: HashSet<(usize, Held)>,
+    /// Diagnostics are only reported once a time warp body has been analysed for every iteration
+    report: bool,
+    diagnostics: Vec<Diagnostic>
+}
+
+impl<'a> InventoryChecker<'a> {
+    pub fn new(statements: &'a [Stmt]) -> Self {
+        Self {
+            statements,
+            names: HashMap::new(),
+            potions: HashSet::new(),
+            bought: HashSet::new(),
+            report: true,
+            diagnostics: Vec::new()
+        }
+    }
+
+    /// Checks the inventories of all actors. Returns the warnings when no errors were found.
+    ///
+    /// Expects the program to be type checked.
+    pub fn check_inventories(&mut self) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let mut inventories = Inventories::new();
+        self.check_all(self.statements, &mut inventories);
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort_by_key(|d| d.span.map(|span| span.start));
+        Diagnostic::ok_if_warnings(diagnostics)
+    }
+
+    fn check_all(&mut self, statements: &'a [Stmt], inventories: &mut Inventories) {
+        statements.iter().for_each(|statement| self.check_statement(statement, inventories))
+    }
+
+    fn check_statement(&mut self, statement: &'a Stmt, inventories: &mut Inventories) {
+        match statement {
+            Stmt::Declaration(declaration) => {
+                if let Object::Char { .. } | Object::Zombie { .. } | Object::Potion { .. } = declaration.object {
+                    self.names.insert(declaration.id, declaration.name.clone());
+                }
+                if let Object::Potion { .. } = declaration.object {
+                    self.potions.insert(declaration.id);
+                }
+            }
+            Stmt::Action(action) => match action.kind {
+                ActionKind::Buys { user, item, .. } => {
+                    let item = self.held(item);
+                    self.bought.insert((user, item));
+                    let count = inventories.entry((user, item)).or_insert(Count::NONE);
+                    count.min = count.min.saturating_add(1);
+                    count.max = count.max.and_then(|max| max.checked_add(1));
+                }
+                ActionKind::Uses { user, potion } => {
+                    let potion = Held::Potion(potion);
+                    self.expect_item(inventories, user, potion, action.span);
+                    // The potion is used up
+                    let count = inventories.entry((user, potion)).or_insert(Count::NONE);
+                    count.min -= 1;
+                    count.max = count.max.map(|max| max - 1);
+                }
+                ActionKind::Attacks { .. } | ActionKind::Shouts { .. } | ActionKind::Whispers { .. } => {}
+                ActionKind::ShoutsSpeak { user, .. } | ActionKind::WhispersSpeak { user, .. } => {
+                    self.expect_item(inventories, user, Held::SpellBook, action.span);
+                }
+            }
+            Stmt::Spell(spell) => {
+                self.expect_item(inventories, spell.user, Held::SpellBook, spell.span);
+                match &spell.kind {
+                    SpellKind::UnZombify { zombie } => {
+                        // The zombie turns into a new character with an empty inventory, unless it
+                        // has no health left
+                        inventories.iter_mut()
+                            .filter(|((actor, _), _)| actor == zombie)
+                            .for_each(|(_, count)| count.min = 0);
+                    }
+                    SpellKind::CreatePotion { potion } => {
+                        // The old potion is replaced with the new one
+                        self.expect_item(inventories, spell.user, Held::Potion(*potion), spell.span);
+                    }
+                    SpellKind::TimeWarp { body, .. } => self.check_time_warp(body, inventories),
+                    SpellKind::Confuse { .. } | SpellKind::GodSpeech | SpellKind::Shift => {}
+                }
+            }
+        }
+    }
+
+    /// The body of a time warp runs any number of times, so the inventories after the time warp
+    /// are the inventories at the start of the loop once they stop changing
+    fn check_time_warp(&mut self, body: &'a [Stmt], inventories: &mut Inventories) {
+        let report = self.report;
+        self.report = false;
+        loop {
+            let mut iteration = inventories.clone();
+            self.check_all(body, &mut iteration);
+            let mut widened = inventories.clone();
+            for (key, count) in iteration {
+                let before = widened.get(&key).copied().unwrap_or(Count::NONE);
+                widened.insert(key, before.widen(count));
+            }
+            let changed = widened != *inventories;
+            *inventories = widened;
+            if !changed {
+                break;
+            }
+        }
+        self.report = report;
+        self.check_all(body, &mut inventories.clone())
+    }
+
+    /// Reports when the actor doesn't own the item. Afterwards, the actor is assumed to own it,
+    /// because the program would have stopped otherwise.
+    fn expect_item(&mut self, inventories: &mut Inventories, actor: usize, item: Held, span: Span) {
+        let count = inventories.entry((actor, item)).or_insert(Count::NONE);
+        let owned = count.min > 0;
+        let never_owned = count.max == Some(0);
+        count.min = count.min.max(1);
+        count.max = count.max.map(|max| max.max(1));
+        if owned || !self.report {
+            return;
+        }
+        let actor_name = self.name(actor);
+        let item_name = match item {
+            Held::Potion(potion) => self.name(potion),
+            Held::SpellBook => "a spellbook".to_string()
+        };
+        let diagnostic = if !never_owned {
+            Diagnostic::warning(ErrorKind::Inventory, format!("{actor_name} may not own {item_name} here."))
+                .with_hint(format!("{actor_name} does not buy {item_name} on every path to this point, or may have used it up already."))
+        } else if self.bought.contains(&(actor, item)) {
+            Diagnostic::new(ErrorKind::Inventory, format!("{actor_name} has no {item_name} left."))
+                .with_hint("Actors need to buy a potion as many times as they use it.")
+        } else {
+            Diagnostic::new(ErrorKind::Inventory, format!("{actor_name} never bought {item_name}."))
+                .with_hint(format!("Let {actor_name} buy {item_name} from a merchant first."))
+        };
+        self.diagnostics.push(diagnostic.with_span(span));
+    }
+
+    fn held(&self, item: usize) -> Held {
+        if self.potions.contains(&item) { Held::Potion(item) } else { Held::SpellBook }
+    }
+
+    fn name(&self, id: usize) -> String {
+        self.names.get(&id).cloned().unwrap_or_else(|| format!("#{id}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{check_inventories, span, span_at, summary};
+    use crate::user_output::Severity;
+
+    const fn count(min: u32, max: Option<u32>) -> Count {
+        Count { min, max }
+    }
+
+    #[test]
+    fn join_keeps_the_bounds_of_both_counts() {
+        assert_eq!(count(1, Some(2)).join(count(0, Some(5))), count(0, Some(5)));
+        assert_eq!(count(2, Some(2)).join(count(3, None)), count(2, None));
+        assert_eq!(Count::NONE.join(Count::NONE), Count::NONE);
+    }
+
+    #[test]
+    fn widen_jumps_to_the_bounds_when_a_count_changes() {
+        // The maximum keeps growing when an actor buys in a loop
+        assert_eq!(count(0, Some(0)).widen(count(1, Some(1))), count(0, None));
+        // The minimum keeps shrinking when an actor uses items in a loop
+        assert_eq!(count(3, Some(3)).widen(count(2, Some(2))), count(0, Some(3)));
+        // Counts that don't change are kept, so the loop converges
+        assert_eq!(count(1, None).widen(count(1, None)), count(1, None));
+        assert_eq!(count(1, Some(4)).widen(count(2, Some(3))), count(1, Some(4)));
+    }
+
+    #[test]
+    fn never_bought_a_spellbook() {
+        let source = ["char a = (1, 1)", "spellbook sb = ()", "a uses sb casting shift()"].join("\n");
+        let diagnostics = check_inventories(&source).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            summary(&diagnostics[0]),
+            (ErrorKind::Inventory, Severity::Error, "a never bought a spellbook.", Some(span(&source, "a uses sb casting shift()")))
+        );
+    }
+
+    #[test]
+    fn bought_a_spellbook() {
+        let source = ["char a = (1, 1)", "spellbook sb = ()", "merchant m = ()", "a buys sb from m", "a uses sb casting shift()", "a shouts sb casting speak()"].join("\n");
+        assert_eq!(check_inventories(&source), Ok(vec![]));
+    }
+
+    #[test]
+    fn potions_are_used_up() {
+        let source = ["char a = (1, 1)", "potion p = (1)", "merchant m = ()", "a buys p from m", "a uses p", "a uses p"].join("\n");
+        let diagnostics = check_inventories(&source).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(summary(&diagnostics[0]), (ErrorKind::Inventory, Severity::Error, "a has no p left.", Some(span_at(&source, "a uses p", 1))));
+    }
+
+    #[test]
+    fn potions_bought_twice_can_be_used_twice() {
+        let source = ["char a = (1, 1)", "potion p = (1)", "merchant m = ()", "a buys p from m", "a buys p from m", "a uses p", "a uses p"].join("\n");
+        assert_eq!(check_inventories(&source), Ok(vec![]));
+    }
+
+    #[test]
+    fn item_bought_in_a_time_warp_may_not_be_owned() {
+        let source = [
+            "char a = (1, 1)",
+            "char counter = (1, 0)",
+            "potion p = (1)",
+            "spellbook sb = ()",
+            "merchant m = ()",
+            "a buys sb from m",
+            "a uses sb casting time_warp(counter)",
+            "\ta buys p from m",
+            "end",
+            "a uses p"
+        ].join("\n");
+        let warnings = check_inventories(&source).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(summary(&warnings[0]), (ErrorKind::Inventory, Severity::Warning, "a may not own p here.", Some(span(&source, "a uses p"))));
+    }
+
+    #[test]
+    fn item_used_in_every_iteration_of_a_time_warp() {
+        // The second iteration may use a potion that was used up in the first one
+        let source = [
+            "char a = (1, 1)",
+            "char counter = (2, 0)",
+            "potion p = (1)",
+            "spellbook sb = ()",
+            "merchant m = ()",
+            "a buys sb from m",
+            "a buys p from m",
+            "a uses sb casting time_warp(counter)",
+            "\ta uses p",
+            "end"
+        ].join("\n");
+        let warnings = check_inventories(&source).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(summary(&warnings[0]), (ErrorKind::Inventory, Severity::Warning, "a may not own p here.", Some(span(&source, "a uses p"))));
+    }
+
+    #[test]
+    fn item_bought_in_every_iteration_of_a_time_warp() {
+        let source = [
+            "char a = (1, 1)",
+            "char counter = (2, 0)",
+            "potion p = (1)",
+            "spellbook sb = ()",
+            "merchant m = ()",
+            "a buys sb from m",
+            "a uses sb casting time_warp(counter)",
+            "\ta buys p from m",
+            "\ta uses p",
+            "end"
+        ].join("\n");
+        assert_eq!(check_inventories(&source), Ok(vec![]));
+    }
+}
@@ -1,10 +1,16 @@
 pub mod user_output;
 mod tokenizer;
 pub use tokenizer::*;
+pub mod ast;
 mod parser;
 pub use parser::*;
-pub mod uid;
+pub mod symbol_table;
 pub mod generator;
 pub mod type_checker;
+pub mod inventory;
+pub mod health;
+pub mod interpreter;
 mod compile;
-pub use compile::*;
\ No newline at end of file
+pub use compile::*;
+#[cfg(test)]
+mod test_util;
@@ -1,512 +1,546 @@
-use simple_colors::blue;
-
-use crate::node::*;
-use crate::{Token, TokenType, uid};
-use crate::user_output::CompileError;
+use crate::ast::*;
+use crate::{compile_error, Token, TokenType};
+use crate::symbol_table::SymbolTable;
+use crate::user_output::{CompileError, Diagnostic, ErrorKind, Span};
 
 pub struct Parser<'a> {
     tokens: &'a mut Vec<Token>,
-    /// Contains the names of all the named objects
-    ids: Vec<(String, usize)>
+    /// Hands out the ids of all declarations and contains the names of all the named objects
+    symbols: SymbolTable,
+    /// The span of the last consumed token
+    last_span: Span,
+    /// Errors that were recovered from
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+    /// Uses of names that were not declared yet, with the kind of object that was expected. They
+    /// are reported once the whole program is parsed, so that a later declaration can be pointed
+    /// out.
+    undeclared: Vec<(&'static str, Token)>
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a mut Vec<Token>) -> Self {
-        Self { tokens, ids: Vec::new() }
+        Self {
+            tokens,
+            symbols: SymbolTable::new(),
+            last_span: Span::default(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            undeclared: Vec::new()
+        }
     }
     
-    pub fn parse(&mut self) -> Vec<Box<dyn Node + Send + Sync>> {
-        let mut nodes: Vec<Box<dyn Node + Send + Sync>> = Vec::new();
+    /// Parses all tokens. When a statement contains an error, parsing resumes at the start of the
+    /// next statement, so that all errors can be reported at once. The errors are returned along
+    /// with the warnings.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+        let mut statements = Vec::new();
         while !self.tokens.is_empty() {
-            nodes.push(self.parse_next_statement());
+            self.parse_recovering(&mut statements);
+        }
+        self.report_undeclared();
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            let mut diagnostics = std::mem::take(&mut self.errors);
+            diagnostics.append(&mut self.warnings);
+            diagnostics.sort_by_key(|d| d.span.map(|span| span.start));
+            Err(diagnostics)
         }
-        nodes
     }
     
-    fn parse_next_statement(&mut self) -> Box<dyn Node + Send + Sync> {
-        // Match all types that can be at the beginning of a statement
-        match self.tokens[0].ttype {
-            TokenType::Char => Box::new(self.parse_char()),
-            TokenType::Zombie => Box::new(self.parse_zombie()),
-            TokenType::Merchant => Box::new(self.parse_merchant()),
-            TokenType::Potion => Box::new(self.parse_potion()),
-            TokenType::SpellBook => Box::new(self.parse_spellbook()),
-            TokenType::Identifier => self.parse_identifier(),
-            other => crate::compile_error!("Unexpected token type at beginning of statement: {}", other.to_string())
+    /// The warnings found while parsing, like shadowed names
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+    
+    /// Parses the next statement into `statements`, or records the error and skips to the start
+    /// of the next statement
+    fn parse_recovering(&mut self, statements: &mut Vec<Stmt>) {
+        let remaining = self.tokens.len();
+        match self.parse_next_statement() {
+            Ok(statement) => statements.push(statement),
+            Err(e) => {
+                self.errors.push(e);
+                if self.tokens.len() == remaining {
+                    // Make sure we don't get stuck on the token that caused the error
+                    self.skip_token();
+                }
+                self.synchronize();
+            }
         }
     }
     
-    fn parse_char(&mut self) -> node::Char {
-        self.consume(TokenType::Char);
-        let name = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::Equals);
-        self.consume(TokenType::OParen);
-        let health = self.consume(TokenType::Integer).value
+    /// Skips tokens until the start of a statement or the end of a time warp body. Time warp
+    /// bodies that are skipped are skipped as a whole.
+    fn synchronize(&mut self) {
+        let mut time_warps = 0;
+        while let Some(ttype) = self.peek_type(0) {
+            match ttype {
+                TokenType::SbFnTimeWarp => time_warps += 1,
+                TokenType::End if time_warps > 0 => time_warps -= 1,
+                TokenType::End => return,
+                _ if time_warps == 0 && self.at_statement_start() => return,
+                _ => {}
+            }
+            self.skip_token();
+        }
+    }
+    
+    /// Returns true if the next tokens are a declaration keyword or an identifier followed by an
+    /// action
+    fn at_statement_start(&self) -> bool {
+        match self.peek_type(0) {
+            Some(TokenType::Char | TokenType::Zombie | TokenType::Merchant | TokenType::Potion | TokenType::SpellBook) => true,
+            Some(TokenType::Identifier) => matches!(
+                self.peek_type(1),
+                Some(TokenType::FnBuys | TokenType::FnAttacks | TokenType::FnShouts | TokenType::FnWhispers | TokenType::FnUses)
+            ),
+            _ => false
+        }
+    }
+    
+    fn skip_token(&mut self) {
+        if !self.tokens.is_empty() {
+            self.last_span = self.tokens.remove(0).span;
+        }
+    }
+    
+    fn parse_next_statement(&mut self) -> Result<Stmt, Diagnostic> {
+        // Match all types that can be at the beginning of a statement
+        Ok(match self.tokens[0].ttype {
+            TokenType::Char => Stmt::Declaration(self.parse_char()?),
+            TokenType::Zombie => Stmt::Declaration(self.parse_zombie()?),
+            TokenType::Merchant => Stmt::Declaration(self.parse_merchant()?),
+            TokenType::Potion => Stmt::Declaration(self.parse_potion()?),
+            TokenType::SpellBook => Stmt::Declaration(self.parse_spellbook()?),
+            TokenType::Identifier => self.parse_identifier()?,
+            other => compile_error!(Syntax, self.tokens[0].span; "Unexpected token type at beginning of statement: {}", other)
+        })
+    }
+    
+    fn parse_char(&mut self) -> Result<Declaration, Diagnostic> {
+        let start = self.consume(TokenType::Char)?.span;
+        let name = self.consume(TokenType::Identifier)?;
+        // The name is known from here on, so that an error in the rest of the declaration
+        // doesn't cause errors in every statement using it
+        let id = self.declare(&name);
+        let name = name.value;
+        self.consume(TokenType::Equals)?;
+        self.consume(TokenType::OParen)?;
+        let health = self.consume(TokenType::Integer)?;
+        let health = health.value
             .parse::<u32>()
-            .expect_compile_error(&format!(
-                "Character {} has an invalid value for its health.\n{} Characters can only have \
-                non-negative health. Their health must be an unsigned 32-bit integer.",
-                name,
-                simple_colors::cyan!("HELP:"))
-            );
-        self.consume(TokenType::Comma);
-        let attack = self.consume(TokenType::Integer).value
+            .or_compile_error(Diagnostic::new(
+                ErrorKind::InvalidValue,
+                format!("Character {} has an invalid value for its health.", name)
+            ).with_hint("Characters can only have non-negative health. Their health must be an \
+                unsigned 32-bit integer.").with_span(health.span))
+            .unwrap_or_else(|e| self.recover(e));
+        self.consume(TokenType::Comma)?;
+        let attack = self.consume(TokenType::Integer)?;
+        let attack = attack.value
             .parse::<u32>()
-            .expect_compile_error(&format!(
-                "Character {} has an invalid value for its attack.\n{} Attack must be non-negative. \
-                Attack is an unsigned 32-bit integer.",
-                name,
-                simple_colors::cyan!("HELP:"))
-            );
-        self.consume(TokenType::CParen);
+            .or_compile_error(Diagnostic::new(
+                ErrorKind::InvalidValue,
+                format!("Character {} has an invalid value for its attack.", name)
+            ).with_hint("Attack must be non-negative. Attack is an unsigned 32-bit integer.").with_span(attack.span))
+            .unwrap_or_else(|e| self.recover(e));
+        self.consume(TokenType::CParen)?;
         
-        let id = uid::generate_uid();
-        self.ids.push((name.clone(), id));
-        
-        node::Char {
+        Ok(Declaration {
             id,
             name,
-            health,
-            attack
-        }
+            object: Object::Char { health, attack },
+            span: start.join(self.last_span)
+        })
     }
     
-    fn parse_zombie(&mut self) -> node::Zombie {
-        self.consume(TokenType::Zombie);
-        let name = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::Equals);
-        self.consume(TokenType::OParen);
-        let health = self.consume(TokenType::Integer).value
+    fn parse_zombie(&mut self) -> Result<Declaration, Diagnostic> {
+        let start = self.consume(TokenType::Zombie)?.span;
+        let name = self.consume(TokenType::Identifier)?;
+        let id = self.declare(&name);
+        let name = name.value;
+        self.consume(TokenType::Equals)?;
+        self.consume(TokenType::OParen)?;
+        let health = self.consume(TokenType::Integer)?;
+        let health = health.value
             .parse::<i32>()
-            .expect_compile_error(&format!(
-                "Zombie {} has an invalid value for its health.\n{} Make sure you have put in an \
-                integer. Zombie's health are signed 32-bit integers.",
-                name,
-                simple_colors::cyan!("HELP:"))
-            );
-        self.consume(TokenType::Comma);
-        let attack = self.consume(TokenType::Integer).value
+            .or_compile_error(Diagnostic::new(
+                ErrorKind::InvalidValue,
+                format!("Zombie {} has an invalid value for its health.", name)
+            ).with_hint("Make sure you have put in an integer. Zombie's health are signed 32-bit \
+                integers.").with_span(health.span))
+            .unwrap_or_else(|e| self.recover(e));
+        self.consume(TokenType::Comma)?;
+        let attack = self.consume(TokenType::Integer)?;
+        let attack = attack.value
             .parse::<u32>()
-            .expect_compile_error(&format!(
-                "Zombie {} has an invalid value for its attack.\n{} Attack must be non-negative. \
-                Attack is an unsigned 32-bit integer.",
-                name,
-                simple_colors::cyan!("HELP:"))
-            );
-        self.consume(TokenType::CParen);
-        
-        let id = uid::generate_uid();
-        self.ids.push((name.clone(), id));
+            .or_compile_error(Diagnostic::new(
+                ErrorKind::InvalidValue,
+                format!("Zombie {} has an invalid value for its attack.", name)
+            ).with_hint("Attack must be non-negative. Attack is an unsigned 32-bit integer.").with_span(attack.span))
+            .unwrap_or_else(|e| self.recover(e));
+        self.consume(TokenType::CParen)?;
         
-        node::Zombie {
+        Ok(Declaration {
             id,
             name,
-            health,
-            attack
-        }
+            object: Object::Zombie { health, attack },
+            span: start.join(self.last_span)
+        })
     }
     
     /// Parses a merchant creation statement
-    fn parse_merchant(&mut self) -> node::Merchant {
-        self.consume(TokenType::Merchant);
-        let name = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::Equals);
-        self.consume(TokenType::OParen);
-        self.consume(TokenType::CParen);
+    fn parse_merchant(&mut self) -> Result<Declaration, Diagnostic> {
+        let start = self.consume(TokenType::Merchant)?.span;
+        let name = self.consume(TokenType::Identifier)?;
+        let id = self.declare(&name);
+        let name = name.value;
+        self.consume(TokenType::Equals)?;
+        self.consume(TokenType::OParen)?;
+        self.consume(TokenType::CParen)?;
         
-        let id = uid::generate_uid();
-        self.ids.push((name.clone(), id));
-        
-        node::Merchant {
+        Ok(Declaration {
             id,
-            name
-        }
+            name,
+            object: Object::Merchant,
+            span: start.join(self.last_span)
+        })
     }
     
     /// Parses a potion creation statement
-    fn parse_potion(&mut self) -> node::Potion {
-        self.consume(TokenType::Potion);
-        let name = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::Equals);
-        self.consume(TokenType::OParen);
-        let value = self.consume(TokenType::Integer).value
+    fn parse_potion(&mut self) -> Result<Declaration, Diagnostic> {
+        let start = self.consume(TokenType::Potion)?.span;
+        let name = self.consume(TokenType::Identifier)?;
+        let id = self.declare(&name);
+        let name = name.value;
+        self.consume(TokenType::Equals)?;
+        self.consume(TokenType::OParen)?;
+        let value = self.consume(TokenType::Integer)?;
+        let value = value.value
             .parse::<u32>()
-            .expect_compile_error(&format!(
-                "Potion {} has an invalid value for its healing value.\n{} Potions can only have \
-                non-negative healing values. Their value must be an unsigned 32-bit integer.",
-                name,
-                simple_colors::cyan!("HELP:"))
-            );
-        self.consume(TokenType::CParen);
-        
-        let id = uid::generate_uid();
-        self.ids.push((name.clone(), id));
+            .or_compile_error(Diagnostic::new(
+                ErrorKind::InvalidValue,
+                format!("Potion {} has an invalid value for its healing value.", name)
+            ).with_hint("Potions can only have non-negative healing values. Their value must be \
+                an unsigned 32-bit integer.").with_span(value.span))
+            .unwrap_or_else(|e| self.recover(e));
+        self.consume(TokenType::CParen)?;
         
-        node::Potion {
+        Ok(Declaration {
             id,
             name,
-            value
-        }
+            object: Object::Potion { value },
+            span: start.join(self.last_span)
+        })
     }
     
     /// Parses a spellbook creation statement
-    fn parse_spellbook(&mut self) -> node::SpellBook {
-        self.consume(TokenType::SpellBook);
-        let name = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::Equals);
-        self.consume(TokenType::OParen);
-        self.consume(TokenType::CParen);
+    fn parse_spellbook(&mut self) -> Result<Declaration, Diagnostic> {
+        let start = self.consume(TokenType::SpellBook)?.span;
+        let name = self.consume(TokenType::Identifier)?;
+        let id = self.declare(&name);
+        let name = name.value;
+        self.consume(TokenType::Equals)?;
+        self.consume(TokenType::OParen)?;
+        self.consume(TokenType::CParen)?;
         
-        let id = uid::generate_uid();
-        self.ids.push((name.clone(), id));
-        
-        node::SpellBook {
+        Ok(Declaration {
             id,
-            name
-        }
+            name,
+            object: Object::SpellBook,
+            span: start.join(self.last_span)
+        })
     }
     
     /// Called when an identifier is at the beginning of a statement
-    fn parse_identifier(&mut self) -> Box<dyn Node + Send + Sync> {
-        let ident = self.consume(TokenType::Identifier);
-        return match {
-            if let Some(t) = self.peek_type(0) { t }
-            else { crate::compile_error!("Expected an action after identifier {}, but got none.", ident.value) }
-        } {
-            TokenType::FnBuys => Box::new(self.parse_fn_buys(&ident)),
-            TokenType::FnAttacks => Box::new(self.parse_fn_attacks(&ident)),
-            TokenType::FnShouts => self.parse_fn_shouts(&ident),
-            TokenType::FnWhispers => self.parse_fn_whispers(&ident),
-            TokenType::FnUses => self.parse_fn_uses(&ident),
-            TokenType::FnCasting => crate::compile_error!("Casting can not be used on its own. It has to be used alongside a `uses` action."),
-            v => crate::compile_error!("Expected an action after identifier {}, but got {}", ident.value, v.to_string())
-        }
+    fn parse_identifier(&mut self) -> Result<Stmt, Diagnostic> {
+        let ident = self.consume(TokenType::Identifier)?;
+        let action = if let Some(t) = self.peek_type(0) { t }
+            else { compile_error!(Syntax, ident.span; "Expected an action after identifier {}, but got none.", ident.value) };
+        Ok(match action {
+            TokenType::FnBuys => Stmt::Action(self.parse_fn_buys(&ident)?),
+            TokenType::FnAttacks => Stmt::Action(self.parse_fn_attacks(&ident)?),
+            TokenType::FnShouts => Stmt::Action(self.parse_fn_shouts(&ident)?),
+            TokenType::FnWhispers => Stmt::Action(self.parse_fn_whispers(&ident)?),
+            TokenType::FnUses => self.parse_fn_uses(&ident)?,
+            TokenType::FnCasting => compile_error!(Syntax, self.tokens[0].span; "Casting can not be used on its own. It has to be used alongside a `uses` action."),
+            v => compile_error!(Syntax, self.tokens[0].span; "Expected an action after identifier {}, but got {}", ident.value, v)
+        })
     }
     
     /// Parses a buys expression `c1 buys from m1`
-    fn parse_fn_buys(&mut self, ident: &Token) -> node::FnBuys {
-        let user = &ident.value;
-        self.consume(TokenType::FnBuys);
-        let item = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::From);
-        let merchant = self.consume(TokenType::Identifier).value;
-        let user_id = &self.ids.iter().find_map(|obj| {
-            if &obj.0 == user {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No character {} exists.\n{} Define the character before you use it.", user, blue!("HINT:")));
+    fn parse_fn_buys(&mut self, ident: &Token) -> Result<Action, Diagnostic> {
+        self.consume(TokenType::FnBuys)?;
+        let item = self.consume(TokenType::Identifier)?;
+        self.consume(TokenType::From)?;
+        let merchant = self.consume(TokenType::Identifier)?;
+        let user_id = self.resolve("character", ident);
         // Note: Parser does not check if the right type is given, only if the ident exists!
-        let item_id = &self.ids.iter().find_map(|obj| {
-            if &obj.0 == &item {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No item {} exists.\n{} Define the item before you use it.", item, blue!("HINT:")));
+        let item_id = self.resolve("item", &item);
         // Same note
-        let merchant_id = &self.ids.iter().find_map(|obj| {
-            if &obj.0 == &merchant {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No merchant {} exists.\n{} Define the merchant before you use it.", merchant, blue!("HINT:")));
+        let merchant_id = self.resolve("merchant", &merchant);
         
-        node::FnBuys {
-            id: uid::generate_uid(),
-            user: *user_id,
-            item: *item_id,
-            merchant: *merchant_id
-        }
+        Ok(Action {
+            kind: ActionKind::Buys { user: user_id, item: item_id, merchant: merchant_id },
+            span: ident.span.join(self.last_span)
+        })
     }
     
     /// Parses an attacks expression `c1 attacks c2`
-    fn parse_fn_attacks(&mut self, ident: &Token) -> node::FnAttacks {
-        let attacker = &ident.value;
-        self.consume(TokenType::FnAttacks);
-        let attacked = self.consume(TokenType::Identifier).value;
-        let attacker_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == attacker {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No character {} exists.\n{} Define the character before you use it.", attacker, blue!("HINT:")));
-        let attacked_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == &attacked {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No character {} exists.\n{} Define the character before you use it.", attacked, blue!("HINT:")));
+    fn parse_fn_attacks(&mut self, ident: &Token) -> Result<Action, Diagnostic> {
+        self.consume(TokenType::FnAttacks)?;
+        let attacked = self.consume(TokenType::Identifier)?;
+        let attacker_id = self.resolve("character", ident);
+        let attacked_id = self.resolve("character", &attacked);
         
-        node::FnAttacks {
-            id: uid::generate_uid(),
-            attacked: attacked_id,
-            attacker: attacker_id
-        }
+        Ok(Action {
+            kind: ActionKind::Attacks { attacker: attacker_id, attacked: attacked_id },
+            span: ident.span.join(self.last_span)
+        })
     }
     
-    fn parse_fn_uses(&mut self, ident: &Token) -> Box<dyn Node + Send + Sync> {
-        let user = &ident.value;
-        self.consume(TokenType::FnUses);
-        let user_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == user {
-                Some(obj.1)
-            } else {
-                None
-            }
-        })
-            .expect_compile_error(&format!("No character {} exists.\n{} Define the character before you use it.", user, blue!("HINT:")));
-        let item_or_sb = self.consume(TokenType::Identifier).value;
-        let item_or_sb_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == &item_or_sb {
-                Some(obj.1)
-            } else {
-                None
-            }
-        })
-            .expect_compile_error(&format!("No item or spellbook {} exists.\n{} Define the item or spellbook before you use it.", item_or_sb, blue!("HINT:")));
+    fn parse_fn_uses(&mut self, ident: &Token) -> Result<Stmt, Diagnostic> {
+        self.consume(TokenType::FnUses)?;
+        let user_id = self.resolve("character", ident);
+        let item_or_sb = self.consume(TokenType::Identifier)?;
+        let item_or_sb_id = self.resolve("item or spellbook", &item_or_sb);
         if let Ok(is_casting) = self.peek(TokenType::FnCasting, 0) {
             if is_casting {
                 // Spellbook use
-                self.consume(TokenType::FnCasting);
+                self.consume(TokenType::FnCasting)?;
                 if let Some(spell) = self.peek_type(0) {
-                    return match spell {
-                        TokenType::SbFnUnZombify => Box::new(self.parse_spell_un_zombify(user_id, item_or_sb_id)),
-                        TokenType::SbFnConfuse => Box::new(self.parse_spell_confuse(user_id, item_or_sb_id)),
-                        TokenType::SbFnGodSpeech => Box::new(self.parse_spell_god_speech(user_id, item_or_sb_id)),
-                        TokenType::SbFnTimeWarp => Box::new(self.parse_spell_time_warp(user_id, item_or_sb_id)),
-                        TokenType::SbFnShift => Box::new(self.parse_spell_shift(user_id, item_or_sb_id)),
-                        TokenType::SbFnCreatePotion => Box::new(self.parse_spell_pot(user_id, item_or_sb_id)),
-                        val => crate::compile_error!("{}", &format!("Invalid spellbook spell: {}", val.to_string()))
-                    }
+                    let kind = match spell {
+                        TokenType::SbFnUnZombify => self.parse_spell_un_zombify()?,
+                        TokenType::SbFnConfuse => self.parse_spell_confuse()?,
+                        TokenType::SbFnGodSpeech => self.parse_spell_god_speech()?,
+                        TokenType::SbFnTimeWarp => self.parse_spell_time_warp(ident.span)?,
+                        TokenType::SbFnShift => self.parse_spell_shift()?,
+                        TokenType::SbFnCreatePotion => self.parse_spell_pot()?,
+                        val => compile_error!(Syntax, self.tokens[0].span; "Invalid spellbook spell: {}", val)
+                    };
+                    return Ok(Stmt::Spell(Spell {
+                        user: user_id,
+                        spell_book: item_or_sb_id,
+                        kind,
+                        span: ident.span.join(self.last_span)
+                    }));
                 }
             }
         }
         // Item (potion) use
-        Box::new(
-            FnUses {
-                id: uid::generate_uid(),
-                user: user_id,
-                item: item_or_sb_id
-            }
-        )
-    }
-    
-    fn parse_spell_pot(&mut self, user: usize, sb: usize) -> node::FnUsesCasting {
-        self.consume(TokenType::SbFnCreatePotion);
-        self.consume(TokenType::OParen);
-        let potion = self.consume(TokenType::Identifier).value;
-        let potion_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == &potion {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No potion {} exists.\n{} Define the potion before you use it.", potion, blue!("HINT:")));
-        self.consume(TokenType::CParen);
+        Ok(Stmt::Action(Action {
+            kind: ActionKind::Uses { user: user_id, potion: item_or_sb_id },
+            span: ident.span.join(self.last_span)
+        }))
+    }
+    
+    fn parse_spell_pot(&mut self) -> Result<SpellKind, Diagnostic> {
+        self.consume(TokenType::SbFnCreatePotion)?;
+        self.consume(TokenType::OParen)?;
+        let potion = self.consume(TokenType::Identifier)?;
+        let potion_id = self.resolve("potion", &potion);
+        self.consume(TokenType::CParen)?;
         
-        node::FnUsesCasting {
-            id: uid::generate_uid(),
-            user,
-            spell_book: sb,
-            function: SBFunction::CreatePot,
-            parameter: Some(potion_id),
-            body: None
-        }
+        Ok(SpellKind::CreatePotion { potion: potion_id })
     }
     
-    fn parse_spell_shift(&mut self, user: usize, sb: usize) -> node::FnUsesCasting {
-        self.consume(TokenType::SbFnShift);
-        self.consume(TokenType::OParen);
-        self.consume(TokenType::CParen);
+    fn parse_spell_shift(&mut self) -> Result<SpellKind, Diagnostic> {
+        self.consume(TokenType::SbFnShift)?;
+        self.consume(TokenType::OParen)?;
+        self.consume(TokenType::CParen)?;
     
-        node::FnUsesCasting {
-            id: uid::generate_uid(),
-            user,
-            spell_book: sb,
-            function: SBFunction::Shift,
-            parameter: None,
-            body: None
-        }
+        Ok(SpellKind::Shift)
     }
     
-    fn parse_spell_un_zombify(&mut self, user: usize, sb: usize) -> node::FnUsesCasting {
-        self.consume(TokenType::SbFnUnZombify);
-        self.consume(TokenType::OParen);
-        let zombie = self.consume(TokenType::Identifier).value;
-        let zombie_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == &zombie {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No zombie {} exists.\n{} Define the zombie before you use it.", zombie, blue!("HINT:")));
-        self.consume(TokenType::CParen);
+    fn parse_spell_un_zombify(&mut self) -> Result<SpellKind, Diagnostic> {
+        self.consume(TokenType::SbFnUnZombify)?;
+        self.consume(TokenType::OParen)?;
+        let zombie = self.consume(TokenType::Identifier)?;
+        let zombie_id = self.resolve("zombie", &zombie);
+        self.consume(TokenType::CParen)?;
         
-        node::FnUsesCasting {
-            id: uid::generate_uid(),
-            user,
-            spell_book: sb,
-            function: SBFunction::UnZombify,
-            parameter: Some(zombie_id),
-            body: None
-        }
+        Ok(SpellKind::UnZombify { zombie: zombie_id })
     }
     
-    fn parse_spell_confuse(&mut self, user: usize, sb: usize) -> node::FnUsesCasting {
-        self.consume(TokenType::SbFnConfuse);
-        self.consume(TokenType::OParen);
-        let confused_char = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::CParen);
-        let confused_char_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == &confused_char {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No character or zombie {} exists.\n{} Define the character or zombie before you use it.", confused_char, blue!("HINT:")));
+    fn parse_spell_confuse(&mut self) -> Result<SpellKind, Diagnostic> {
+        self.consume(TokenType::SbFnConfuse)?;
+        self.consume(TokenType::OParen)?;
+        let confused_char = self.consume(TokenType::Identifier)?;
+        self.consume(TokenType::CParen)?;
+        let confused_char_id = self.resolve("character or zombie", &confused_char);
         
-        node::FnUsesCasting {
-            id: uid::generate_uid(),
-            user,
-            spell_book: sb,
-            function: SBFunction::Confuse,
-            parameter: Some(confused_char_id),
-            body: None
-        }
+        Ok(SpellKind::Confuse { actor: confused_char_id })
     }
     
-    fn parse_spell_god_speech(&mut self, user: usize, sb: usize) -> node::FnUsesCasting {
-        self.consume(TokenType::SbFnGodSpeech);
-        self.consume(TokenType::OParen);
-        self.consume(TokenType::CParen);
-        node::FnUsesCasting {
-            id: uid::generate_uid(),
-            user,
-            spell_book: sb,
-            function: SBFunction::GodSpeech,
-            parameter: None,
-            body: None
-        }
+    fn parse_spell_god_speech(&mut self) -> Result<SpellKind, Diagnostic> {
+        self.consume(TokenType::SbFnGodSpeech)?;
+        self.consume(TokenType::OParen)?;
+        self.consume(TokenType::CParen)?;
+        
+        Ok(SpellKind::GodSpeech)
+    }
+    
+    fn parse_spell_time_warp(&mut self, start: Span) -> Result<SpellKind, Diagnostic> {
+        self.consume(TokenType::SbFnTimeWarp)?;
+        // An invalid header should not cause the body to be parsed as top level statements
+        let consumed_id = self.parse_time_warp_offer().unwrap_or_else(|e| {
+            self.errors.push(e);
+            self.synchronize();
+            0
+        });
+        // Declarations in the body are consumed at the end of an iteration
+        self.symbols.enter_scope();
+        let body = self.parse_time_warp_body(start);
+        self.symbols.exit_scope();
+        
+        Ok(SpellKind::TimeWarp { consumed: consumed_id, body: body? })
     }
     
-    fn parse_spell_time_warp(&mut self, user: usize, sb: usize) -> node::FnUsesCasting {
-        self.consume(TokenType::SbFnTimeWarp);
-        self.consume(TokenType::OParen);
-        let consumed = self.consume(TokenType::Identifier).value;
-        self.consume(TokenType::CParen);
-        let consumed_id = self.ids.iter().find_map(|obj| {
-            if &obj.0 == &consumed {
-                Some(obj.1)
-            } else {
-                None
-            }
-        }).expect_compile_error(&format!("No character {} exists.\n{} Define the character before you use it.", consumed, blue!("HINT:")));
-        let mut body: Vec<Box<dyn Node + Send + Sync>> = Vec::new();
-        while !self.peek(TokenType::End, 0).expect_compile_error("Expected time warp loop to end with `end`, but got none.") {
-            body.push(self.parse_next_statement());
-        }
-        self.consume(TokenType::End);
-        node::FnUsesCasting {
-            id: uid::generate_uid(),
-            user,
-            spell_book: sb,
-            function: SBFunction::TimeWarp,
-            parameter: Some(consumed_id),
-            body: Some(node::FnBody {
-                id: uid::generate_uid(),
-                body
-            })
+    fn parse_time_warp_body(&mut self, start: Span) -> Result<Vec<Stmt>, Diagnostic> {
+        let mut body = Vec::new();
+        while !self.peek(TokenType::End, 0).or_compile_error(Diagnostic::new(
+            ErrorKind::Syntax,
+            "Expected time warp loop to end with `end`, but got none."
+        ).with_span(start.join(self.last_span)))? {
+            self.parse_recovering(&mut body);
         }
+        self.consume(TokenType::End)?;
+        Ok(body)
+    }
+    
+    /// Parses the `(offer)` of a time warp and returns the id of the offer
+    fn parse_time_warp_offer(&mut self) -> Result<usize, Diagnostic> {
+        self.consume(TokenType::OParen)?;
+        let consumed = self.consume(TokenType::Identifier)?;
+        self.consume(TokenType::CParen)?;
+        Ok(self.resolve("character", &consumed))
     }
     
     /// Includes regular shout and spellbook speak version
-    fn parse_fn_shouts(&mut self, ident: &Token) -> Box<dyn Node + Send + Sync> {
-        self.consume(TokenType::FnShouts);
-        let user = &ident.value;
-        let user_id = self.ids.iter().find_map(|obj| if &obj.0 == user { Some(obj.1) } else { None })
-            .expect_compile_error(&format!("No character {} exists.\n{} Define the character before you use it.", user, blue!("HINT:")));
+    fn parse_fn_shouts(&mut self, ident: &Token) -> Result<Action, Diagnostic> {
+        self.consume(TokenType::FnShouts)?;
+        let user_id = self.resolve("character", ident);
         if let Ok(is_casting) = self.peek(TokenType::FnCasting, 1) {
             if let Ok(is_speak) = self.peek(TokenType::SbFnSpeak, 2) {
                 if is_casting && is_speak {
-                    let spellbook = self.consume(TokenType::Identifier).value;
-                    let sb_id = self.ids.iter().find_map(|obj| if &obj.0 == &spellbook { Some(obj.1) } else { None })
-                        .expect_compile_error(&format!("No spellbook {} exists.\n{} Define the spellbook before you use it.", spellbook, blue!("HINT:")));
-                    self.consume(TokenType::FnCasting);
-                    self.consume(TokenType::SbFnSpeak);
-                    self.consume(TokenType::OParen);
-                    self.consume(TokenType::CParen);
-                    return Box::new(node::FnShoutsSpeak {
-                        id: uid::generate_uid(),
-                        user: user_id,
-                        spell_book: sb_id
+                    let spell_book = self.parse_speak()?;
+                    return Ok(Action {
+                        kind: ActionKind::ShoutsSpeak { user: user_id, spell_book },
+                        span: ident.span.join(self.last_span)
                     });
                 }
             }
         }
-        Box::new(
-            node::FnShouts {
-                id: uid::generate_uid(),
-                user: user_id
-            }
-        )
+        Ok(Action {
+            kind: ActionKind::Shouts { user: user_id },
+            span: ident.span.join(self.last_span)
+        })
     }
     
-    fn parse_fn_whispers(&mut self, ident: &Token) -> Box<dyn Node + Send + Sync> {
-        self.consume(TokenType::FnWhispers);
-        let user = &ident.value;
-        let user_id = self.ids.iter().find_map(|obj| if &obj.0 == user { Some(obj.1) } else { None })
-            .expect_compile_error(&format!("No character {} exists.\n{} Define the character before you use it.", user, blue!("HINT:")));
+    fn parse_fn_whispers(&mut self, ident: &Token) -> Result<Action, Diagnostic> {
+        self.consume(TokenType::FnWhispers)?;
+        let user_id = self.resolve("character", ident);
         if let Ok(is_casting) = self.peek(TokenType::FnCasting, 1) {
             if let Ok(is_speak) = self.peek(TokenType::SbFnSpeak, 2) {
                 if is_casting && is_speak {
-                    let spellbook = self.consume(TokenType::Identifier).value;
-                    let sb_id = self.ids.iter().find_map(|obj| if &obj.0 == &spellbook { Some(obj.1) } else { None })
-                        .expect_compile_error(&format!("No spellbook {} exists.\n{} Define the spellbook before you use it.", spellbook, blue!("HINT:")));
-                    self.consume(TokenType::FnCasting);
-                    self.consume(TokenType::SbFnSpeak);
-                    self.consume(TokenType::OParen);
-                    self.consume(TokenType::CParen);
-                    return Box::new(node::FnWhispersSpeak {
-                        id: uid::generate_uid(),
-                        user: user_id,
-                        spell_book: sb_id
+                    let spell_book = self.parse_speak()?;
+                    return Ok(Action {
+                        kind: ActionKind::WhispersSpeak { user: user_id, spell_book },
+                        span: ident.span.join(self.last_span)
                     });
                 }
             }
         }
-        Box::new(
-            node::FnWhispers {
-                id: uid::generate_uid(),
-                user: user_id
-            }
-        )
+        Ok(Action {
+            kind: ActionKind::Whispers { user: user_id },
+            span: ident.span.join(self.last_span)
+        })
+    }
+    
+    /// Parses `sb1 casting speak()` and returns the id of the spellbook
+    fn parse_speak(&mut self) -> Result<usize, Diagnostic> {
+        let spellbook = self.consume(TokenType::Identifier)?;
+        let sb_id = self.resolve("spellbook", &spellbook);
+        self.consume(TokenType::FnCasting)?;
+        self.consume(TokenType::SbFnSpeak)?;
+        self.consume(TokenType::OParen)?;
+        self.consume(TokenType::CParen)?;
+        Ok(sb_id)
+    }
+    
+    /// Returns the id of the object the identifier refers to. If it doesn't exist, the error is
+    /// recorded and parsing continues with an invalid id.
+    fn resolve(&mut self, kind: &'static str, ident: &Token) -> usize {
+        if let Some(symbol) = self.symbols.lookup(&ident.value) {
+            return symbol.id;
+        }
+        if let Some(symbol) = self.symbols.find(&ident.value) {
+            // Only declarations in a time warp body that has ended are not visible
+            let e = Diagnostic::new(ErrorKind::UndefinedName, format!("{} is not visible here.", ident.value))
+                .with_hint("Objects declared in a time warp only exist until the end of the iteration.")
+                .with_span(ident.span)
+                .with_note(symbol.span, format!("{} is declared inside a time warp here", ident.value));
+            return self.recover(e);
+        }
+        self.undeclared.push((kind, ident.clone()));
+        0
+    }
+    
+    /// Declares the object with the given name, warning when it shadows an earlier declaration
+    fn declare(&mut self, name: &Token) -> usize {
+        if let Some(shadowed) = self.symbols.lookup(&name.value) {
+            self.warnings.push(
+                Diagnostic::warning(ErrorKind::Shadowing, format!("{} shadows an earlier declaration.", name.value))
+                    .with_hint(format!("From here on, {} refers to this declaration. This is fine if the earlier {} is dead.", name.value, name.value))
+                    .with_span(name.span)
+                    .with_note(shadowed.span, format!("{} was declared here", name.value))
+            );
+        }
+        self.symbols.declare(&name.value, name.span)
+    }
+    
+    /// Reports the uses of names that were never declared before them
+    fn report_undeclared(&mut self) {
+        for (kind, ident) in std::mem::take(&mut self.undeclared) {
+            let e = match self.symbols.find(&ident.value) {
+                Some(symbol) => Diagnostic::new(ErrorKind::UndefinedName, format!("{} is used before it is declared.", ident.value))
+                    .with_hint(format!("Move the declaration of {} above its first use.", ident.value))
+                    .with_span(ident.span)
+                    .with_note(symbol.span, format!("{} is declared here", ident.value)),
+                None => undefined(kind, &ident)
+            };
+            self.errors.push(e);
+        }
+    }
+    
+    /// Records an error that doesn't prevent the rest of the statement from being parsed
+    fn recover<T: Default>(&mut self, e: Diagnostic) -> T {
+        self.errors.push(e);
+        T::default()
     }
     
     /// Consumes the next token
     ///
-    /// Panics if the next token does not match the expected_token_type
-    fn consume(&mut self, expected_type: TokenType) -> Token {
-        let token = self.tokens.remove(0);
+    /// Returns an error if the next token does not match the expected_token_type
+    fn consume(&mut self, expected_type: TokenType) -> Result<Token, Diagnostic> {
+        if self.tokens.is_empty() {
+            compile_error!(Syntax, self.last_span; "Expected token type {} but got none", expected_type)
+        }
+        let token = &self.tokens[0];
         if token.ttype == expected_type {
-            token
+            self.last_span = token.span;
+            Ok(self.tokens.remove(0))
         } else {
-            crate::compile_error!("Expected token type {} but got {}", expected_type.to_string(), token.ttype.to_string())
+            compile_error!(Syntax, token.span; "Expected token type {} but got {}", expected_type, token.ttype)
         }
     }
     
     /// Returns the token type of the next token. Returns None if there is no token at the given
     /// `offset` position.
     fn peek_type(&self, offset: usize) -> Option<TokenType> {
-        if let Some(token) = self.tokens.get(offset) {
-            return Some(token.ttype);
-        } else {
-            None
-        }
+        self.tokens.get(offset).map(|token| token.ttype)
     }
     
     /// Peeks at the token at the index of `offset` and returns true if the expected
@@ -522,163 +556,134 @@ impl<'a> Parser<'a> {
     }
 }
 
-pub mod node {
-    use std::any::Any;
-    use std::fmt;
-    use crate::{impl_node, new_node};
-    
-    new_node!(Char, name: String, health: u32, attack: u32);
-    
-    new_node!(Zombie, name: String, health: i32, attack: u32);
-    
-    new_node!(Merchant, name: String);
-    
-    new_node!(Potion, name: String, value: u32);
-    impl Item for Potion {}
-    
-    new_node!(SpellBook, name: String);
-    impl Item for SpellBook {}
-    
-    // new_node!(FnBuys, user: Char, item: Box<dyn BuyableNode>, merchant: Merchant);
-    // Fields: id's of the nodes
-    new_node!(FnBuys, user: usize, item: usize, merchant: usize);
-    
-    // The item here is a potion
-    new_node!(FnUses, user: usize, item: usize);
-    
-    new_node!(FnUsesCasting, user: usize, spell_book: usize, function: SBFunction, parameter: Option<usize>, body: Option<FnBody>);
-    
-    new_node!(FnBody, body: Vec<Box<dyn Node + Send + Sync>>);
-    
-    new_node!(FnAttacks, attacked: usize, attacker: usize);
-    
-    new_node!(FnShouts, user: usize);
-    new_node!(FnShoutsSpeak, user: usize, spell_book: usize);
-    
-    new_node!(FnWhispers, user: usize);
-    new_node!(FnWhispersSpeak, user: usize, spell_book: usize);
-    
-    #[derive(Debug, PartialEq)]
-    pub enum NodeType {
-        Char,
-        Zombie,
-        Merchant,
-        Potion,
-        SpellBook,
-        FnBuys,
-        FnAttacks,
-        FnUses,
-        FnShouts,
-        /// `c1 shouts sb1 casting speak()`
-        FnShoutsSpeak,
-        FnWhispers,
-        /// `c1 shouts sb1 casting speak()`
-        FnWhispersSpeak,
-        /// A spell book function cast
-        FnUsesCasting,
-        /// The body of a `FnUsesCasting` statement of type `TimeWarp`.
-        /// "SpellBody"
-        FnBody
-    }
-    
-    #[derive(Clone, Copy, Debug)]
-    /// Spell book functions
-    pub enum SBFunction {
-        UnZombify,
-        Confuse,
-        GodSpeech,
-        TimeWarp,
-        Shift,
-        CreatePot
-        // Speak is always in a shouts or whispers
-    }
-    
-    #[macro_export]
-    macro_rules! new_node {
-        ( $name: ident, $($field: ident: $type: ty),* ) => (
-            #[derive(Debug, Clone)]
-            pub struct $name {
-                pub id: usize,
-                $(pub $field: $type),*
-            }
-            
-            unsafe impl Send for $name {}
-            unsafe impl Sync for $name {}
-            
-            impl Node for $name {
-                impl_node!{NodeType::$name}
-            }
-        )
-    }
-    
-    #[macro_export]
-    /// Implements node methods
-    macro_rules! impl_node {
-        ( $type: expr ) => (
-            fn get_type(&self) -> NodeType {
-                $type
-            }
-        
-            fn as_any(&self) -> &dyn Any {
-                self
-            }
-            
-            fn get_id(&self) -> usize {
-                self.id
-            }
-        )
-    }
-    
-    pub trait Node: fmt::Debug + NodeClone + Send + Sync {
-        fn get_type(&self) -> NodeType;
-        fn as_any(&self) -> &dyn Any;
-        fn get_id(&self) -> usize;
+/// The error for an object that is used before it is defined
+fn undefined(kind: &str, name: &Token) -> Diagnostic {
+    Diagnostic::new(ErrorKind::UndefinedName, format!("No {} {} exists.", kind, name.value))
+        .with_hint(format!("Define the {} before you use it.", kind))
+        .with_span(name.span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ActionKind;
+    use crate::test_util::{actions, span, span_at, summary, try_parse};
+    use crate::user_output::Severity;
+
+    #[test]
+    fn every_syntax_error_is_reported() {
+        let source = [
+            "char a = 1, 1)",
+            "spellbook sb = ()",
+            "merchant m = ()",
+            "a buys sb from m",
+            "a uses sb casting time_warp(a)",
+            "\ta attacks 1",
+            "\ta shouts",
+            "end",
+            "zombie z = (1,)",
+            "a whispers"
+        ].join("\n");
+        let errors = try_parse(&source).0.unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(summary(&errors[0]), (ErrorKind::Syntax, Severity::Error, "Expected token type '(' but got integer", Some(span(&source, "1"))));
+        // The error inside the time warp doesn't end its body early
+        assert_eq!(summary(&errors[1]), (ErrorKind::Syntax, Severity::Error, "Expected token type identifier but got integer", Some(span_at(&source, "1", 2))));
+        assert_eq!(summary(&errors[2]), (ErrorKind::Syntax, Severity::Error, "Expected token type integer but got ')'", Some(span_at(&source, ")", 4))));
     }
-    
-    pub trait Item: fmt::Debug + ItemClone {}
-    
-    /// Implements Clone for Box<dyn Node + Send + Sync>
-    pub trait NodeClone {
-        fn clone_box(&self) -> Box<dyn Node + Send + Sync>;
+
+    #[test]
+    fn parsing_resumes_at_the_next_statement() {
+        let source = ["char a = (1 1)", "char b = (1, 1)", "c shouts", "b shouts"].join("\n");
+        let errors = try_parse(&source).0.unwrap_err();
+        // The rest of the declaration is skipped, `b` is declared and `b shouts` is valid
+        assert_eq!(errors.len(), 2);
+        assert_eq!(summary(&errors[0]), (ErrorKind::Syntax, Severity::Error, "Expected token type ',' but got integer", Some(span_at(&source, "1", 1))));
+        assert_eq!(summary(&errors[1]), (ErrorKind::UndefinedName, Severity::Error, "No character c exists.", Some(span(&source, "c"))));
     }
-    
-    impl<T> NodeClone for T
-        where T: 'static + Node + Clone
-    {
-        fn clone_box(&self) -> Box<dyn Node + Send + Sync> {
-            Box::new(self.clone())
-        }
+
+    #[test]
+    fn names_resolve_to_the_most_recent_declaration() {
+        let source = ["char a = (1, 1)", "a shouts", "char a = (2, 1)", "a shouts"].join("\n");
+        let (parsed, warnings) = try_parse(&source);
+        assert_eq!(actions(&parsed.unwrap()), [(0, ActionKind::Shouts { user: 1 }), (0, ActionKind::Shouts { user: 2 })]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            summary(&warnings[0]),
+            (ErrorKind::Shadowing, Severity::Warning, "a shadows an earlier declaration.", Some(span_at(&source, "a", 2)))
+        );
+        assert_eq!(warnings[0].notes[0].span, span(&source, "a"));
     }
-    
-    impl Clone for Box<dyn Node + Send + Sync> {
-        fn clone(&self) -> Box<dyn Node + Send + Sync> {
-            self.clone_box()
-        }
+
+    #[test]
+    fn different_names_dont_shadow() {
+        let source = ["char a = (1, 1)", "char b = (1, 1)", "b attacks a"].join("\n");
+        let (parsed, warnings) = try_parse(&source);
+        assert!(parsed.is_ok());
+        assert!(warnings.is_empty());
     }
-    
-    pub trait ItemClone {
-        fn clone_box(&self) -> Box<dyn Item>;
+
+    #[test]
+    fn name_used_before_its_declaration() {
+        let source = ["char a = (1, 1)", "a attacks b", "char b = (1, 1)"].join("\n");
+        let diagnostics = try_parse(&source).0.unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            summary(&diagnostics[0]),
+            (ErrorKind::UndefinedName, Severity::Error, "b is used before it is declared.", Some(span(&source, "b")))
+        );
+        assert_eq!(diagnostics[0].notes[0].span, span_at(&source, "b", 1));
     }
-    
-    impl<T> ItemClone for T
-        where T: 'static + Item + Clone
-    {
-        fn clone_box(&self) -> Box<dyn Item> {
-            Box::new(self.clone())
-        }
+
+    #[test]
+    fn name_that_is_never_declared() {
+        let source = ["char a = (1, 1)", "a attacks b"].join("\n");
+        let diagnostics = try_parse(&source).0.unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(summary(&diagnostics[0]), (ErrorKind::UndefinedName, Severity::Error, "No character b exists.", Some(span(&source, "b"))));
+        assert!(diagnostics[0].notes.is_empty());
     }
-    
-    impl Clone for Box<dyn Item> {
-        fn clone(&self) -> Box<dyn Item> {
-            self.clone_box()
-        }
+
+    #[test]
+    fn declarations_in_a_time_warp_are_not_visible_after_it() {
+        let source = [
+            "char caster = (1, 1)",
+            "char counter = (1, 1)",
+            "spellbook sb = ()",
+            "caster uses sb casting time_warp(counter)",
+            "\tchar a = (1, 1)",
+            "\ta shouts",
+            "end",
+            "a shouts"
+        ].join("\n");
+        let diagnostics = try_parse(&source).0.unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            summary(&diagnostics[0]),
+            (ErrorKind::UndefinedName, Severity::Error, "a is not visible here.", Some(span_at(&source, "a", 2)))
+        );
+        assert_eq!(diagnostics[0].notes[0].span, span(&source, "a"));
     }
-    
-    pub fn parse_dyn_node<NodeType: 'static>(n: &dyn Node) -> &NodeType {
-        let parse_node: &NodeType = match n.as_any().downcast_ref::<NodeType>() {
-            Some(n) => n,
-            None => crate::compile_error!("Expected wrong type")
-        };
-        parse_node
+
+    #[test]
+    fn time_warp_bodies_shadow_the_outer_declarations() {
+        let source = [
+            "char a = (1, 1)",
+            "char counter = (1, 1)",
+            "spellbook sb = ()",
+            "a uses sb casting time_warp(counter)",
+            "\tchar a = (2, 1)",
+            "\ta shouts",
+            "end",
+            "a shouts"
+        ].join("\n");
+        let (parsed, warnings) = try_parse(&source);
+        // The use in the body refers to the declaration in the body, the one after it to the first
+        assert_eq!(actions(&parsed.unwrap()), [(1, ActionKind::Shouts { user: 4 }), (0, ActionKind::Shouts { user: 1 })]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            summary(&warnings[0]),
+            (ErrorKind::Shadowing, Severity::Warning, "a shadows an earlier declaration.", Some(span_at(&source, "a", 2)))
+        );
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,119 @@
+use crate::user_output::Span;
+
+/// Hands out the ids of a single compilation and resolves names to the declared objects.
+///
+/// Ids are handed out in order, so compiling the same source twice gives the same ids.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    /// The last id that was handed out
+    last_id: usize,
+    /// Every declaration, in order of declaration
+    symbols: Vec<Symbol>,
+    /// The indices in `symbols` of the declarations visible in each open scope, the innermost
+    /// scope is last
+    scopes: Vec<Vec<usize>>
+}
+
+/// A declared object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub id: usize,
+    pub name: String,
+    /// Where the object was declared
+    pub span: Span
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self { scopes: vec![Vec::new()], ..Self::default() }
+    }
+
+    /// Generates a uid, which is just a counter.
+    ///
+    /// Will not return a uid of 0, because the counter is incremented before it is returned.
+    pub fn generate_uid(&mut self) -> usize {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Declares an object with the given name in the innermost scope and returns its id
+    pub fn declare(&mut self, name: &str, span: Span) -> usize {
+        let id = self.generate_uid();
+        self.symbols.push(Symbol { id, name: name.to_string(), span });
+        let index = self.symbols.len() - 1;
+        self.scopes.last_mut().expect("The outermost scope is never closed").push(index);
+        id
+    }
+
+    /// Returns the most recent declaration with the given name that is visible in the current
+    /// scope
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.iter().rev().find(|&&index| self.symbols[index].name == name))
+            .map(|&index| &self.symbols[index])
+    }
+
+    /// Returns the most recent declaration with the given name, including the ones that are no
+    /// longer visible
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().rev().find(|symbol| symbol.name == name)
+    }
+
+    /// Opens a nested scope, e.g. the body of a time warp
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Closes the innermost scope, the declarations in it are no longer visible
+    pub fn exit_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_finds_the_most_recent_declaration() {
+        let mut symbols = SymbolTable::new();
+        symbols.declare("a", Span::default());
+        let b = symbols.declare("b", Span::default());
+        let a = symbols.declare("a", Span::default());
+        assert_eq!(symbols.lookup("a").map(|symbol| symbol.id), Some(a));
+        assert_eq!(symbols.lookup("b").map(|symbol| symbol.id), Some(b));
+        assert_eq!(symbols.lookup("c"), None);
+    }
+
+    #[test]
+    fn nested_scopes_see_the_outer_declarations() {
+        let mut symbols = SymbolTable::new();
+        let outer = symbols.declare("a", Span::default());
+        symbols.enter_scope();
+        assert_eq!(symbols.lookup("a").map(|symbol| symbol.id), Some(outer));
+        let inner = symbols.declare("a", Span::default());
+        assert_eq!(symbols.lookup("a").map(|symbol| symbol.id), Some(inner));
+        symbols.exit_scope();
+        assert_eq!(symbols.lookup("a").map(|symbol| symbol.id), Some(outer));
+    }
+
+    #[test]
+    fn declarations_of_closed_scopes_are_only_found() {
+        let mut symbols = SymbolTable::new();
+        symbols.enter_scope();
+        let inner = symbols.declare("a", Span::default());
+        symbols.exit_scope();
+        assert_eq!(symbols.lookup("a"), None);
+        assert_eq!(symbols.find("a").map(|symbol| symbol.id), Some(inner));
+    }
+
+    #[test]
+    fn the_outermost_scope_is_never_closed() {
+        let mut symbols = SymbolTable::new();
+        let a = symbols.declare("a", Span::default());
+        symbols.exit_scope();
+        assert_eq!(symbols.lookup("a").map(|symbol| symbol.id), Some(a));
+    }
+}
@@ -0,0 +1,78 @@
+//! Helpers for the unit tests.
+
+use crate::ast::{ActionKind, Spell, SpellKind, Stmt};
+use crate::user_output::{Diagnostic, ErrorKind, Severity, Span};
+use crate::{Parser, Tokenizer};
+use crate::inventory::InventoryChecker;
+use crate::type_checker::TypeChecker;
+
+/// Parses the program, panicking when it contains a syntax error
+pub fn parse(source: &str) -> Vec<Stmt> {
+    try_parse(source).0.unwrap()
+}
+
+/// Parses the program, returning the result of the parser along with its warnings
+pub fn try_parse(source: &str) -> (Result<Vec<Stmt>, Vec<Diagnostic>>, Vec<Diagnostic>) {
+    let mut tokens = Tokenizer::new(source).tokenize().unwrap();
+    let mut parser = Parser::new(&mut tokens);
+    let parsed = parser.parse();
+    (parsed, parser.warnings().to_vec())
+}
+
+/// The actions of the program, along with the amount of time warps around them
+pub fn actions(program: &[Stmt]) -> Vec<(usize, ActionKind)> {
+    fn walk(program: &[Stmt], depth: usize, actions: &mut Vec<(usize, ActionKind)>) {
+        for stmt in program {
+            match stmt {
+                Stmt::Action(action) => actions.push((depth, action.kind)),
+                Stmt::Spell(Spell { kind: SpellKind::TimeWarp { body, .. }, .. }) => walk(body, depth + 1, actions),
+                _ => {}
+            }
+        }
+    }
+    let mut actions = Vec::new();
+    walk(program, 0, &mut actions);
+    actions
+}
+
+/// The span of the first occurrence of `text` in the source that doesn't start or end inside a
+/// word, so `a` is not found in `char`
+pub fn span(source: &str, text: &str) -> Span {
+    span_at(source, text, 0)
+}
+
+/// [`span`] of the occurrence of `text` after skipping `skip` of them
+pub fn span_at(source: &str, text: &str, skip: usize) -> Span {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = source.match_indices(text)
+        .map(|(start, _)| start)
+        .filter(|&start| {
+            let starts_in_word = is_word(source[..start].chars().last()) && is_word(text.chars().next());
+            let ends_in_word = is_word(source[start + text.len()..].chars().next()) && is_word(text.chars().last());
+            !starts_in_word && !ends_in_word
+        })
+        .nth(skip)
+        .expect("The text is in the source");
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    Span {
+        start,
+        end: start + text.len(),
+        line: source[..start].matches('\n').count() + 1,
+        column: source[line_start..start].chars().count() + 1
+    }
+}
+
+/// The parts of a diagnostic the tests compare
+pub fn summary(diagnostic: &Diagnostic) -> (ErrorKind, Severity, &str, Option<Span>) {
+    (diagnostic.kind, diagnostic.severity, diagnostic.message.as_str(), diagnostic.span)
+}
+
+/// Parses the program and checks its types
+pub fn check_types(source: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    TypeChecker::new(&parse(source)).check_types()
+}
+
+/// Parses the program and checks the inventories of its actors
+pub fn check_inventories(source: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    InventoryChecker::new(&parse(source)).check_inventories()
+}
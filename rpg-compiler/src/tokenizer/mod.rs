@@ -1,4 +1,3 @@
-mod rm_comments;
+#[allow(clippy::module_inception)]
 mod tokenizer;
-pub use rm_comments::rm_comments;
 pub use tokenizer::*;
\ No newline at end of file
@@ -1,92 +1,160 @@
-use std::fmt::{Debug, Formatter};
-use lazy_static::lazy_static;
-use regex::Regex;
+use std::fmt::{Debug, Display, Formatter};
 use TokenType::*;
 use crate::compile_error;
+use crate::user_output::{Diagnostic, ErrorKind, Span};
 
-lazy_static! {
-    /// All token types and their regexes
-    static ref TOKEN_TYPES: [TokenRegex; 26] = [
-        TokenRegex { ttype: Char, regex: Regex::new(r"\A\bchar\b").unwrap() },
-        TokenRegex { ttype: Zombie, regex: Regex::new(r"\A\bzombie\b").unwrap() },
-        TokenRegex { ttype: Merchant, regex: Regex::new(r"\A\bmerchant\b").unwrap() },
-        TokenRegex { ttype: Potion, regex: Regex::new(r"\A\bpotion\b").unwrap() },
-        TokenRegex { ttype: SpellBook, regex: Regex::new(r"\A\bspellbook\b").unwrap() },
-        TokenRegex { ttype: End, regex: Regex::new(r"\A\bend\b").unwrap() },
-        TokenRegex { ttype: FnAttacks, regex: Regex::new(r"\A\battacks\b").unwrap() },
-        TokenRegex { ttype: FnShouts, regex: Regex::new(r"\A\bshouts\b").unwrap() },
-        TokenRegex { ttype: FnWhispers, regex: Regex::new(r"\A\bwhispers\b").unwrap() },
-        TokenRegex { ttype: FnBuys, regex: Regex::new(r"\A\bbuys\b").unwrap() },
-        TokenRegex { ttype: FnUses, regex: Regex::new(r"\A\buses\b").unwrap() },
-        TokenRegex { ttype: FnCasting, regex: Regex::new(r"\A\bcasting\b").unwrap() },
-        TokenRegex { ttype: SbFnSpeak, regex: Regex::new(r"\A\bspeak\b").unwrap() },
-        TokenRegex { ttype: SbFnUnZombify, regex: Regex::new(r"\A\bun_zombify\b").unwrap() },
-        TokenRegex { ttype: SbFnConfuse, regex: Regex::new(r"\A\bconfuse\b").unwrap() },
-        TokenRegex { ttype: SbFnGodSpeech, regex: Regex::new(r"\A\bgod_speech\b").unwrap() },
-        TokenRegex { ttype: SbFnTimeWarp, regex: Regex::new(r"\A\btime_warp\b").unwrap() },
-        TokenRegex { ttype: SbFnShift, regex: Regex::new(r"\A\bshift\b").unwrap() },
-        TokenRegex { ttype: SbFnCreatePotion, regex: Regex::new(r"\A\bcreate_potion\b").unwrap() },
-        TokenRegex { ttype: From, regex: Regex::new(r"\A\bfrom\b").unwrap() },
-        // Identifier also matches all of the above, which is why it should be below all of them
-        // This means that all of the above are reserved words
-        TokenRegex { ttype: Identifier, regex: Regex::new(r"\A\b[a-zA-Z_]\w*\b").unwrap() },
-        TokenRegex { ttype: Integer, regex: Regex::new(r"\A-?[0-9]+").unwrap() },
-        TokenRegex { ttype: Equals, regex: Regex::new(r"\A=").unwrap() },
-        TokenRegex { ttype: OParen, regex: Regex::new(r"\A\(").unwrap() },
-        TokenRegex { ttype: CParen, regex: Regex::new(r"\A\)").unwrap() },
-        TokenRegex { ttype: Comma, regex: Regex::new(r"\A,").unwrap() },
-    ];
-}
-
-/// Tokenizes an input string
+/// Tokenizes an input string in a single pass
 pub struct Tokenizer<'a> {
-    code: &'a str
+    /// The complete source code
+    source: &'a str,
+    /// Byte offset of the part of the source code that has not been tokenized yet
+    pos: usize,
+    /// The line of `pos`
+    line: usize,
+    /// The column of `pos` in characters
+    column: usize
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(code: &'a str) -> Tokenizer<'a> {
-        Self { code }
+        Self { source: code, pos: 0, line: 1, column: 1 }
+    }
+    
+    /// Tokenizes the code, leaving out comments
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, Diagnostic> {
+        Ok(self.tokenize_with_trivia()?.into_iter().filter(|token| !token.ttype.is_trivia()).collect())
     }
     
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    /// Tokenizes the code, keeping the comments for tools that need them, like a formatter
+    pub fn tokenize_with_trivia(&mut self) -> Result<Vec<Token>, Diagnostic> {
         let mut tokens: Vec<Token> = Vec::new();
-        while !self.code.is_empty() {
-            tokens.push(self.tokenize_next());
-            self.code = self.code.trim();
+        self.skip_whitespace();
+        while self.pos < self.source.len() {
+            tokens.push(self.tokenize_next()?);
+            self.skip_whitespace();
         }
-        tokens
+        Ok(tokens)
     }
     
-    fn tokenize_next(&mut self) -> Token {
-        for token_type in TOKEN_TYPES.iter() {
-            // m = match
-            for m in token_type.regex.captures_iter(&self.code) {
-                // We will only have 1 match because of \A
-                if let Some(_match) = m.get(0) {
-                    let m = _match.as_str().to_string();
-                    self.code = self.code.strip_prefix(m.as_str()).expect("Unexpected error: could not strip match from code");
-                    return Token::new(token_type.ttype, m);
-                }
+    fn tokenize_next(&mut self) -> Result<Token, Diagnostic> {
+        let start = self.pos;
+        let (line, column) = (self.line, self.column);
+        let ttype = match self.peek(0) {
+            Some('=') => { self.bump(); Equals }
+            Some('(') => { self.bump(); OParen }
+            Some(')') => { self.bump(); CParen }
+            Some(',') => { self.bump(); Comma }
+            Some('#') if self.peek(1) == Some('[') => {
+                self.skip_block_comment(Span { start, end: start + 2, line, column })?;
+                BlockComment
+            }
+            Some('#') => {
+                self.bump_while(|c| c != '\n' && c != '\r');
+                LineComment
+            }
+            // A minus sign is only valid as the start of a negative integer
+            Some('-') if self.peek(1).is_some_and(|c| c.is_ascii_digit()) => {
+                self.bump();
+                self.bump_while(|c| c.is_ascii_digit());
+                Integer
+            }
+            Some(c) if c.is_ascii_digit() => {
+                self.bump_while(|c| c.is_ascii_digit());
+                Integer
+            }
+            // Identifiers start with a letter or an underscore, followed by any amount of letters,
+            // digits and underscores. Letters and digits from any script are allowed.
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                self.bump_while(|c| c.is_alphanumeric() || c == '_');
+                keyword(&self.source[start..self.pos]).unwrap_or(Identifier)
+            }
+            _ => {
+                let first_token = self.source[start..].split(char::is_whitespace).next().unwrap_or("");
+                let span = Span { start, end: start + first_token.len(), line, column };
+                compile_error!(UnknownToken, span; "Unexpected token: found {}", first_token)
+            }
+        };
+        let span = Span { start, end: self.pos, line, column };
+        Ok(Token::new(ttype, self.source[start..self.pos].to_string(), span))
+    }
+    
+    /// Returns the character `n` characters after the remaining code
+    fn peek(&self, n: usize) -> Option<char> {
+        self.source[self.pos..].chars().nth(n)
+    }
+    
+    /// Moves past the next character, keeping track of the line and column we're on
+    fn bump(&mut self) {
+        if let Some(c) = self.peek(0) {
+            self.pos += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
             }
         }
-        
-        // Have no matches
-        let first_token = self.code.split(|c| c == ' ').collect::<Vec<&str>>();
-        if let Some(first_token) = first_token.get(0) {
-            compile_error!("Unexpected token: found {}", first_token)
-        } else {
-            compile_error!("Expected token but got None")
+    }
+    
+    fn bump_while(&mut self, f: impl Fn(char) -> bool) {
+        while self.peek(0).is_some_and(&f) {
+            self.bump();
+        }
+    }
+    
+    /// Moves past a block comment, block comments can be nested
+    fn skip_block_comment(&mut self, open: Span) -> Result<(), Diagnostic> {
+        let mut depth = 0;
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some('#'), Some('[')) => depth += 1,
+                (Some(']'), Some('#')) => depth -= 1,
+                (Some(_), _) => {
+                    self.bump();
+                    continue;
+                }
+                (None, _) => return Err(Diagnostic::new(ErrorKind::Syntax, "Block comment is never closed.")
+                    .with_hint("Close the comment using ]#")
+                    .with_span(open))
+            }
+            self.bump();
+            self.bump();
+            if depth == 0 {
+                return Ok(());
+            }
         }
     }
+    
+    fn skip_whitespace(&mut self) {
+        self.bump_while(char::is_whitespace);
+    }
 }
 
-/// Contains the regex for a token and its type
-#[derive(Debug)]
-pub struct TokenRegex {
-    /// Token type
-    pub ttype: TokenType,
-    /// A regex in perl-compatible syntax
-    pub regex: Regex
+/// Returns the type of a reserved word
+fn keyword(word: &str) -> Option<TokenType> {
+    Some(match word {
+        "char" => Char,
+        "zombie" => Zombie,
+        "merchant" => Merchant,
+        "potion" => Potion,
+        "spellbook" => SpellBook,
+        "end" => End,
+        "attacks" => FnAttacks,
+        "shouts" => FnShouts,
+        "whispers" => FnWhispers,
+        "buys" => FnBuys,
+        "uses" => FnUses,
+        "casting" => FnCasting,
+        "speak" => SbFnSpeak,
+        "un_zombify" => SbFnUnZombify,
+        "confuse" => SbFnConfuse,
+        "god_speech" => SbFnGodSpeech,
+        "time_warp" => SbFnTimeWarp,
+        "shift" => SbFnShift,
+        "create_potion" => SbFnCreatePotion,
+        "from" => From,
+        _ => return None
+    })
 }
 
 /// Represents a single token
@@ -95,12 +163,21 @@ pub struct Token {
     /// Token type
     pub ttype: TokenType,
     /// token value
-    pub value: String
+    pub value: String,
+    /// Where the token is in the source code
+    pub span: Span
 }
 
 impl Token {
-    pub fn new(t: TokenType, v: String) -> Self {
-        Self { ttype: t, value: v }
+    pub fn new(t: TokenType, v: String, span: Span) -> Self {
+        Self { ttype: t, value: v, span }
+    }
+}
+
+/// `line:column type "value"`, e.g. `1:1 char "char"`
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} {} {:?}", self.span.line, self.span.column, self.ttype, self.value)
     }
 }
 
@@ -143,55 +220,137 @@ pub enum TokenType {
     OParen,
     /// (
     CParen,
-    Comma
+    Comma,
+    // Trivia
+    /// `# ...` until the end of the line
+    LineComment,
+    /// `#[ ... ]#`
+    BlockComment
 }
 
 impl Debug for TokenType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
+        write!(f, "{}", self)
     }
 }
 
-impl ToString for TokenType {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Char => "char".to_string(),
-            Self::Zombie => "zombie".to_string(),
-            Self::Merchant => "merchant".to_string(),
-            Self::Potion => "potion".to_string(),
-            Self::SpellBook => "spellbook".to_string(),
-            Self::End => "end".to_string(),
-            Self::FnAttacks => "attacks".to_string(),
-            Self::FnShouts => "shouts".to_string(),
-            Self::FnWhispers => "whispers".to_string(),
-            Self::FnBuys => "buys".to_string(),
-            Self::FnUses => "uses".to_string(),
-            Self::FnCasting => "casting".to_string(),
-            Self::SbFnSpeak => "speak()".to_string(),
-            Self::SbFnUnZombify => "un_zombify()".to_string(),
-            Self::SbFnConfuse => "confuse()".to_string(),
-            Self::SbFnGodSpeech => "god_speech()".to_string(),
-            Self::SbFnTimeWarp => "time_warp()".to_string(),
-            Self::SbFnShift => "shift()".to_string(),
-            Self::SbFnCreatePotion => "create_potion()".to_string(),
-            Self::From => "from".to_string(),
-            Self::Identifier => "identifier".to_string(),
-            Self::Integer => "integer".to_string(),
-            Self::Equals => "'='".to_string(),
-            Self::OParen => "'('".to_string(),
-            Self::CParen => "')'".to_string(),
-            Self::Comma => "','".to_string(),
-        }
+impl Display for TokenType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Char => "char",
+            Self::Zombie => "zombie",
+            Self::Merchant => "merchant",
+            Self::Potion => "potion",
+            Self::SpellBook => "spellbook",
+            Self::End => "end",
+            Self::FnAttacks => "attacks",
+            Self::FnShouts => "shouts",
+            Self::FnWhispers => "whispers",
+            Self::FnBuys => "buys",
+            Self::FnUses => "uses",
+            Self::FnCasting => "casting",
+            Self::SbFnSpeak => "speak()",
+            Self::SbFnUnZombify => "un_zombify()",
+            Self::SbFnConfuse => "confuse()",
+            Self::SbFnGodSpeech => "god_speech()",
+            Self::SbFnTimeWarp => "time_warp()",
+            Self::SbFnShift => "shift()",
+            Self::SbFnCreatePotion => "create_potion()",
+            Self::From => "from",
+            Self::Identifier => "identifier",
+            Self::Integer => "integer",
+            Self::Equals => "'='",
+            Self::OParen => "'('",
+            Self::CParen => "')'",
+            Self::Comma => "','",
+            Self::LineComment => "comment",
+            Self::BlockComment => "block comment",
+        };
+        write!(f, "{}", s)
     }
 }
 
-#[allow(unused)]
 impl TokenType {
+    /// Tokens that don't change the meaning of the program
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::LineComment | Self::BlockComment)
+    }
+    
+    #[allow(unused)]
     fn formatted(&self) -> String {
         match self {
-            Self::Identifier => { format!("an {}", self.to_string()) }
-            Self::Integer => { format!("an {}", self.to_string()) }
+            Self::Identifier => { format!("an {}", self) }
+            Self::Integer => { format!("an {}", self) }
             _ => self.to_string()
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::summary;
+    use crate::user_output::Severity;
+
+    fn types(code: &str) -> Vec<TokenType> {
+        Tokenizer::new(code).tokenize().unwrap().into_iter().map(|token| token.ttype).collect()
+    }
+
+    #[test]
+    fn tokenizes_every_kind_of_token() {
+        assert_eq!(types("char a = (1, -2)"), [Char, Identifier, Equals, OParen, Integer, Comma, Integer, CParen]);
+        assert_eq!(types("a uses sb casting time_warp(b)\nend"), [Identifier, FnUses, Identifier, FnCasting, SbFnTimeWarp, OParen, Identifier, CParen, End]);
+        assert_eq!(types("a buys p from m"), [Identifier, FnBuys, Identifier, From, Identifier]);
+    }
+
+    #[test]
+    fn keywords_are_whole_words() {
+        assert_eq!(types("chars end_ shouts2 _uses"), [Identifier, Identifier, Identifier, Identifier]);
+    }
+
+    #[test]
+    fn spans_count_columns_in_characters() {
+        let tokens = Tokenizer::new("char héroïne = (1, 1)\n\théroïne shouts").tokenize().unwrap();
+        assert_eq!(tokens[1].value, "héroïne");
+        assert_eq!(tokens[1].span, Span { start: 5, end: 14, line: 1, column: 6 });
+        assert_eq!(tokens[8].span, Span { start: 25, end: 34, line: 2, column: 2 });
+        assert_eq!(tokens[9].span, Span { start: 35, end: 41, line: 2, column: 10 });
+    }
+
+    #[test]
+    fn identifiers_from_any_script() {
+        let tokens = Tokenizer::new("char 勇者_1 = (1, 1)").tokenize().unwrap();
+        assert_eq!(tokens[1].ttype, Identifier);
+        assert_eq!(tokens[1].value, "勇者_1");
+    }
+
+    #[test]
+    fn minus_only_starts_a_negative_integer() {
+        let tokens = Tokenizer::new("-12").tokenize().unwrap();
+        assert_eq!((tokens[0].ttype, tokens[0].value.as_str()), (Integer, "-12"));
+        let error = Tokenizer::new("char a = (- 1, 1)").tokenize().unwrap_err();
+        assert_eq!(summary(&error), (ErrorKind::UnknownToken, Severity::Error, "Unexpected token: found -", Some(Span { start: 10, end: 11, line: 1, column: 11 })));
+    }
+
+    #[test]
+    fn comments_are_trivia() {
+        let code = "# a comment\nchar #[ nested #[ block ]# comment ]# a";
+        assert_eq!(types(code), [Char, Identifier]);
+        let tokens = Tokenizer::new(code).tokenize_with_trivia().unwrap();
+        let types: Vec<TokenType> = tokens.iter().map(|token| token.ttype).collect();
+        assert_eq!(types, [LineComment, Char, BlockComment, Identifier]);
+        assert_eq!(tokens[2].value, "#[ nested #[ block ]# comment ]#");
+    }
+
+    #[test]
+    fn unclosed_block_comment() {
+        let error = Tokenizer::new("char a\n#[ #[ ]#").tokenize().unwrap_err();
+        assert_eq!(summary(&error), (ErrorKind::Syntax, Severity::Error, "Block comment is never closed.", Some(Span { start: 7, end: 9, line: 2, column: 1 })));
+    }
+
+    #[test]
+    fn unknown_token() {
+        let error = Tokenizer::new("char a = (1, 1)\na attacks b!").tokenize().unwrap_err();
+        assert_eq!(summary(&error), (ErrorKind::UnknownToken, Severity::Error, "Unexpected token: found !", Some(Span { start: 27, end: 28, line: 2, column: 12 })));
+    }
+}
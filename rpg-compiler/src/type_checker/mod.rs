@@ -1,174 +1,355 @@
 use std::collections::HashMap;
-use crate::node::{Node, NodeType, parse_dyn_node};
-use crate::node;
-use crate::compile_error;
+use crate::ast::{Action, ActionKind, Declaration, ObjectKind, Spell, SpellKind, Stmt};
+use crate::ast::visit::{self, Visitor};
+use crate::user_output::{Diagnostic, ErrorKind, Span};
+
+/// What an actor is at a point in the program, `un_zombify` turns a zombie into a character
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ActorKind {
+    Char,
+    Zombie,
+    /// A zombie on some paths and a character on others, e.g. when it is un_zombified in a time
+    /// warp
+    Either
+}
+
+impl ActorKind {
+    fn join(self, other: ActorKind) -> ActorKind {
+        if self == other { self } else { ActorKind::Either }
+    }
+}
+
+/// The kinds of objects that are actors
+const ACTORS: &[ObjectKind] = &[ObjectKind::Char, ObjectKind::Zombie];
 
-// TODO: type check shift() & create_potion()
 pub struct TypeChecker<'a> {
-    nodes: &'a Vec<Box<dyn Node + Send + Sync>>,
-    var_map: HashMap<usize, NodeType>
+    statements: &'a [Stmt],
+    /// The kind every object is declared with
+    var_map: HashMap<usize, ObjectKind>,
+    /// The kind of every actor at the current statement
+    actor_kinds: HashMap<usize, ActorKind>,
+    /// All type errors and warnings found so far
+    diagnostics: Vec<Diagnostic>
 }
 impl<'a> TypeChecker<'a> {
-    pub fn new(nodes: &'a Vec<Box<dyn Node + Send + Sync>>) -> Self {
+    pub fn new(statements: &'a [Stmt]) -> Self {
         Self {
-            nodes,
-            var_map: HashMap::new()
+            statements,
+            var_map: HashMap::new(),
+            actor_kinds: HashMap::new(),
+            diagnostics: Vec::new()
         }
     }
-    pub fn check_types(&mut self) {
-        self.check_node_types(self.nodes)
+    /// Checks all statements, returning every type error that was found. Returns the warnings
+    /// when no errors were found.
+    pub fn check_types(&mut self) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        self.visit_stmts(self.statements);
+        Diagnostic::ok_if_warnings(std::mem::take(&mut self.diagnostics))
     }
-    fn check_node_types(&mut self, nodes: &'a Vec<Box<dyn Node + Send + Sync>>) {
-        nodes.iter().for_each(|node| {
-            let node = &**node;
-            match node.get_type() {
-                NodeType::Char => {
-                    let node: &node::Char = parse_dyn_node(node);
-                    self.var_map.insert(node.id, NodeType::Char);
-                }
-                NodeType::Zombie => {
-                    let node: &node::Zombie = parse_dyn_node(node);
-                    self.var_map.insert(node.id, NodeType::Zombie);
-                }
-                NodeType::Merchant => {
-                    let node: &node::Merchant = parse_dyn_node(node);
-                    self.var_map.insert(node.id, NodeType::Merchant);
-                }
-                NodeType::Potion => {
-                    let node: &node::Potion = parse_dyn_node(node);
-                    self.var_map.insert(node.id, NodeType::Potion);
-                }
-                NodeType::SpellBook => {
-                    let node: &node::SpellBook = parse_dyn_node(node);
-                    self.var_map.insert(node.id, NodeType::SpellBook);
-                }
-                NodeType::FnBuys => {
-                    let node: &node::FnBuys = parse_dyn_node(node);
-                    if let Some(user) = self.var_map.get(&node.user) {
-                        if let Some(item) = self.var_map.get(&node.item) {
-                            if let Some(merchant) = self.var_map.get(&node.merchant) {
-                                if user == &NodeType::Char || user == &NodeType::Zombie {
-                                    if item == &NodeType::Potion || item == &NodeType::SpellBook {
-                                        if !(merchant == &NodeType::Merchant) {
-                                            compile_error!("Only merchants can sell items.")
-                                        }
-                                    } else {
-                                        compile_error!("Only potions and spellbooks can be bought from a merchant.")
-                                    }
-                                } else {
-                                    compile_error!("The one buying must be an actor.")
-                                }
-                            } else {
-                                compile_error!("No merchant found while buying.")
-                            }
-                        } else {
-                            compile_error!("Item you are trying to buy was not found.")
-                        }
-                    } else {
-                        compile_error!("Actor that is trying to buy not found.")
-                    }
-                    
-                }
-                NodeType::FnAttacks => {
-                    let node: &node::FnAttacks = parse_dyn_node(node);
-                    if let Some(attacked) = self.var_map.get(&node.attacked) {
-                        if let Some(attacker) = self.var_map.get(&node.attacker) {
-                            if attacked == &NodeType::Char || attacked == &NodeType::Zombie {
-                                if !(attacker == &NodeType::Char || attacker == &NodeType::Zombie) {
-                                    compile_error!("The one attacking is not an actor.")
-                                }
-                            } else {
-                                compile_error!("The one being attacked is not an actor.")
-                            }
-                        } else {
-                            compile_error!("Attacking actor could not be found.")
-                        }
-                    } else {
-                        compile_error!("Actor being attacked could not be found.")
-                    }
-                }
-                NodeType::FnUses => {
-                    let node: &node::FnUses = parse_dyn_node(node);
-                    if let Some(user) = self.var_map.get(&node.user) {
-                        if let Some(potion) = self.var_map.get(&node.item) {
-                            if user == &NodeType::Char || user == &NodeType::Zombie {
-                                if !(potion == &NodeType::Potion) {
-                                    compile_error!("The item being used is not a potion.")
-                                }
-                            } else {
-                                compile_error!("The user of the potion is not an actor.")
-                            }
-                        } else {
-                            compile_error!("The potion being used was not defined.")
-                        }
-                    } else {
-                        compile_error!("The actor using the potion was not defined.");
-                    }
-                }
-                NodeType::FnShouts => {
-                    let node: &node::FnShouts = parse_dyn_node(node);
-                    if let Some(shouter) = self.var_map.get(&node.user) {
-                        if !(shouter == &NodeType::Char || shouter == &NodeType::Zombie) {
-                            compile_error!("The one shouting is not an actor.")
-                        }
-                    } else {
-                        compile_error!("The actor shouting was not defined.")
-                    }
-                }
-                NodeType::FnShoutsSpeak => {
-                    let node: &node::FnShoutsSpeak = parse_dyn_node(node);
-                    if let Some(shouter) = self.var_map.get(&node.user) {
-                        if let Some(spellbook) = self.var_map.get(&node.spell_book) {
-                            if shouter == &NodeType::Char || shouter == &NodeType::Zombie {
-                                if !(spellbook == &NodeType::SpellBook) {
-                                    compile_error!("The actor is not using a spellbook to shout.")
-                                }
-                            } else {
-                                compile_error!("The one shouting is not an actor.")
-                            }
-                        } else {
-                            compile_error!("The spellbook used for speaking was not defined.")
-                        }
-                    } else {
-                        compile_error!("The actor shouting was not defined.")
-                    }
-                }
-                NodeType::FnWhispers => {
-                    let node: &node::FnWhispers = parse_dyn_node(node);
-                    if let Some(whisperer) = self.var_map.get(&node.user) {
-                        if !(whisperer == &NodeType::Char || whisperer == &NodeType::Zombie) {
-                            compile_error!("The one shouting is not an actor.")
-                        }
-                    } else {
-                        compile_error!("The actor shouting was not defined.")
-                    }
+    /// Checks the objects used by an action, reporting every object of the wrong kind
+    fn check_action(&mut self, action: &Action) {
+        let span = action.span;
+        match action.kind {
+            ActionKind::Buys { user, item, merchant } => {
+                self.expect_kind(user, ACTORS, span, "Actor that is trying to buy not found.", "The one buying must be an actor.");
+                self.expect_kind(item, &[ObjectKind::Potion, ObjectKind::SpellBook], span,
+                    "Item you are trying to buy was not found.", "Only potions and spellbooks can be bought from a merchant.");
+                self.expect_kind(merchant, &[ObjectKind::Merchant], span, "No merchant found while buying.", "Only merchants can sell items.");
+            }
+            ActionKind::Attacks { attacker, attacked } => {
+                self.expect_kind(attacker, ACTORS, span, "Attacking actor could not be found.", "The one attacking is not an actor.");
+                self.expect_kind(attacked, ACTORS, span, "Actor being attacked could not be found.", "The one being attacked is not an actor.");
+            }
+            ActionKind::Uses { user, potion } => {
+                self.expect_kind(user, ACTORS, span, "The actor using the potion was not defined.", "The user of the potion is not an actor.");
+                self.expect_kind(potion, &[ObjectKind::Potion], span, "The potion being used was not defined.", "The item being used is not a potion.");
+            }
+            ActionKind::Shouts { user } | ActionKind::Whispers { user } => {
+                self.expect_kind(user, ACTORS, span, "The actor shouting was not defined.", "The one shouting is not an actor.");
+            }
+            ActionKind::ShoutsSpeak { user, spell_book } | ActionKind::WhispersSpeak { user, spell_book } => {
+                self.expect_kind(user, ACTORS, span, "The actor shouting was not defined.", "The one shouting is not an actor.");
+                self.expect_kind(spell_book, &[ObjectKind::SpellBook], span,
+                    "The spellbook used for speaking was not defined.", "The actor is not using a spellbook to shout.");
+            }
+        }
+    }
+    /// Checks the caster, spellbook and parameter of a spell, reporting every object of the wrong
+    /// kind
+    fn check_spell(&mut self, spell: &Spell) {
+        let span = spell.span;
+        self.expect_kind(spell.user, ACTORS, span, "The actor casting the spell was not defined.", "The one casting the spell is not an actor.");
+        self.expect_kind(spell.spell_book, &[ObjectKind::SpellBook], span,
+            "The spellbook used for casting was not defined.", "The actor is not using a spellbook to cast a spell.");
+        let (parameter, expected, error): (usize, &[ObjectKind], &str) = match spell.kind {
+            // Whether the actor is still a zombie is checked by `check_actor_kind`
+            SpellKind::UnZombify { zombie } => (zombie, ACTORS, "Only zombies can be un_zombified."),
+            SpellKind::Confuse { actor } => (actor, ACTORS, "Only actors can be confused."),
+            SpellKind::TimeWarp { consumed, .. } => (consumed, ACTORS, "Only characters can be offered to a time warp."),
+            SpellKind::CreatePotion { potion } => (potion, &[ObjectKind::Potion], "Only potions can be created."),
+            SpellKind::GodSpeech | SpellKind::Shift => return
+        };
+        self.expect_kind(parameter, expected, span, "The parameter of the spell was not defined.", error);
+    }
+    /// Reports `undefined` when the object was not declared, or `wrong_kind` when it is not one of
+    /// the `expected` kinds
+    fn expect_kind(&mut self, object: usize, expected: &[ObjectKind], span: Span, undefined: &str, wrong_kind: &str) {
+        let diagnostic = match self.var_map.get(&object) {
+            Some(kind) if expected.contains(kind) => return,
+            Some(_) => Diagnostic::new(ErrorKind::Type, wrong_kind),
+            None => Diagnostic::new(ErrorKind::UndefinedName, undefined)
+        };
+        self.diagnostics.push(diagnostic.with_span(span));
+    }
+    /// Reports when an actor that has to be a character is a zombie at this point of the program,
+    /// or may still be one
+    fn check_actor_kind(&mut self, actor: usize, message: &str, span: Span) {
+        let diagnostic = match self.actor_kinds.get(&actor) {
+            Some(ActorKind::Zombie) => Diagnostic::new(ErrorKind::Type, format!("{message}."))
+                .with_hint("Turn the zombie into a character first using un_zombify."),
+            Some(ActorKind::Either) => Diagnostic::warning(ErrorKind::Type, format!("{message}, but the actor may still be a zombie."))
+                .with_hint("The zombie is only un_zombified on some paths to this point, e.g. in a time warp."),
+            Some(ActorKind::Char) | None => return
+        };
+        self.diagnostics.push(diagnostic.with_span(span));
+    }
+    /// Reports when a zombie that is un_zombified has already turned into a character
+    fn check_zombie(&mut self, zombie: usize, span: Span) {
+        let diagnostic = match self.actor_kinds.get(&zombie) {
+            Some(ActorKind::Char) => Diagnostic::new(ErrorKind::Type, "Only zombies can be un_zombified.")
+                .with_hint("The actor is a character, either from the start or because it was un_zombified before."),
+            Some(ActorKind::Either) => Diagnostic::warning(ErrorKind::Type, "Only zombies can be un_zombified, but the actor may already be a character.")
+                .with_hint("The zombie is already un_zombified on some paths to this point, e.g. in a time warp."),
+            Some(ActorKind::Zombie) | None => return
+        };
+        self.diagnostics.push(diagnostic.with_span(span));
+    }
+}
+
+impl<'a> Visitor<'a> for TypeChecker<'a> {
+    fn visit_declaration(&mut self, declaration: &'a Declaration) {
+        self.var_map.insert(declaration.id, declaration.object.kind());
+        match declaration.object.kind() {
+            ObjectKind::Char => self.actor_kinds.insert(declaration.id, ActorKind::Char),
+            ObjectKind::Zombie => self.actor_kinds.insert(declaration.id, ActorKind::Zombie),
+            _ => None
+        };
+    }
+    
+    fn visit_action(&mut self, action: &'a Action) {
+        self.check_action(action);
+        if let ActionKind::ShoutsSpeak { user, .. } | ActionKind::WhispersSpeak { user, .. } = action.kind {
+            self.check_actor_kind(user, "Only characters can speak", action.span);
+        }
+    }
+    
+    fn visit_spell(&mut self, spell: &'a Spell) {
+        self.check_spell(spell);
+        match &spell.kind {
+            SpellKind::UnZombify { zombie } => {
+                self.check_zombie(*zombie, spell.span);
+                // A zombie without health disappears instead, using it afterwards is a runtime error
+                if let Some(kind) = self.actor_kinds.get_mut(zombie) {
+                    *kind = ActorKind::Char;
                 }
-                NodeType::FnWhispersSpeak => {
-                    let node: &node::FnWhispersSpeak = parse_dyn_node(node);
-                    if let Some(whisperer) = self.var_map.get(&node.user) {
-                        if let Some(spellbook) = self.var_map.get(&node.spell_book) {
-                            if whisperer == &NodeType::Char || whisperer == &NodeType::Zombie {
-                                if !(spellbook == &NodeType::SpellBook) {
-                                    compile_error!("The actor is not using a spellbook to shout.")
-                                }
-                            } else {
-                                compile_error!("The one shouting is not an actor.")
-                            }
-                        } else {
-                            compile_error!("The spellbook used for speaking was not defined.")
-                        }
-                    } else {
-                        compile_error!("The actor shouting was not defined.")
+            }
+            SpellKind::TimeWarp { consumed, body } => {
+                self.check_actor_kind(*consumed, "Only characters can be offered to a time warp", spell.span);
+                // The body runs any number of times, so the zombies that are un_zombified in the
+                // body may be characters at its start
+                let mut un_zombified = UnZombified::default();
+                un_zombified.visit_stmts(body);
+                for zombie in un_zombified.0 {
+                    if let Some(kind) = self.actor_kinds.get_mut(&zombie) {
+                        *kind = kind.join(ActorKind::Char);
                     }
                 }
-                NodeType::FnUsesCasting => {
-                    // TODO
-                }
-                NodeType::FnBody => {
-                    let node: &node::FnBody = parse_dyn_node(node);
-                    self.check_node_types(&node.body);
+                let start = self.actor_kinds.clone();
+                visit::walk_spell(self, spell);
+                // The body may not run at all
+                for (actor, kind) in start {
+                    self.actor_kinds.entry(actor).and_modify(|k| *k = k.join(kind)).or_insert(kind);
                 }
             }
-        });
+            SpellKind::CreatePotion { .. } => {
+                self.check_actor_kind(spell.user, "Only characters can create potions", spell.span);
+            }
+            SpellKind::Confuse { .. } | SpellKind::GodSpeech | SpellKind::Shift => {}
+        }
+    }
+}
+
+/// Collects the zombies that are un_zombified, also in nested time warps
+#[derive(Default)]
+struct UnZombified(Vec<usize>);
+
+impl<'ast> Visitor<'ast> for UnZombified {
+    fn visit_spell(&mut self, spell: &'ast Spell) {
+        if let SpellKind::UnZombify { zombie } = spell.kind {
+            self.0.push(zombie);
+        }
+        visit::walk_spell(self, spell);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{check_types, span, summary};
+    use crate::user_output::{Diagnostic, ErrorKind, Severity};
+
+    #[test]
+    fn every_wrong_object_of_a_spell_is_reported() {
+        let source = "char a = (1, 1)\npotion p = (1)\nmerchant m = ()\na uses p casting un_zombify(m)";
+        let diagnostics = check_types(source).unwrap_err();
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, ["The actor is not using a spellbook to cast a spell.", "Only zombies can be un_zombified."]);
+        for diagnostic in &diagnostics {
+            assert_eq!(diagnostic.kind, ErrorKind::Type);
+            assert_eq!(diagnostic.span, Some(span(source, "a uses p casting un_zombify(m)")));
+        }
+    }
+
+    #[test]
+    fn every_wrong_object_of_an_action_is_reported() {
+        let source = "char a = (1, 1)\npotion p = (1)\nmerchant m = ()\nm buys a from p";
+        let diagnostics = check_types(source).unwrap_err();
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, [
+            "The one buying must be an actor.",
+            "Only potions and spellbooks can be bought from a merchant.",
+            "Only merchants can sell items."
+        ]);
+    }
+
+    /// Declares one object of every kind, `a` owns the spellbook
+    const OBJECTS: [&str; 7] = [
+        "char a = (1, 1)",
+        "zombie z = (1, 1)",
+        "potion p = (1)",
+        "spellbook sb = ()",
+        "merchant m = ()",
+        "char counter = (1, 1)",
+        "a buys sb from m"
+    ];
+
+    /// Checks the objects followed by the statement, returning the diagnostics of the statement
+    fn check_statement(statement: &str) -> (String, Vec<Diagnostic>) {
+        let source = OBJECTS.iter().chain([&statement]).copied().collect::<Vec<_>>().join("\n");
+        let diagnostics = check_types(&source).unwrap_or_else(|errors| errors);
+        (source, diagnostics)
+    }
+
+    /// Asserts that the statement has a single type error with the message
+    fn assert_type_error(statement: &str, message: &str) {
+        let (source, diagnostics) = check_statement(statement);
+        let expected = [(ErrorKind::Type, Severity::Error, message, Some(span(&source, statement)))];
+        assert_eq!(diagnostics.iter().map(summary).collect::<Vec<_>>(), expected, "{statement}");
+    }
+
+    fn assert_no_diagnostics(statement: &str) {
+        assert_eq!(check_statement(statement).1, vec![], "{statement}");
+    }
+
+    #[test]
+    fn caster_is_an_actor() {
+        assert_type_error("m uses sb casting shift()", "The one casting the spell is not an actor.");
+        assert_type_error("p uses sb casting god_speech()", "The one casting the spell is not an actor.");
+        assert_no_diagnostics("a uses sb casting shift()");
+        assert_no_diagnostics("z uses sb casting god_speech()");
+    }
+
+    #[test]
+    fn spell_is_cast_with_a_spellbook() {
+        assert_type_error("a uses m casting shift()", "The actor is not using a spellbook to cast a spell.");
+        assert_type_error("a uses z casting god_speech()", "The actor is not using a spellbook to cast a spell.");
+    }
+
+    #[test]
+    fn only_actors_are_un_zombified() {
+        assert_type_error("a uses sb casting un_zombify(m)", "Only zombies can be un_zombified.");
+        assert_type_error("a uses sb casting un_zombify(sb)", "Only zombies can be un_zombified.");
+        assert_no_diagnostics("a uses sb casting un_zombify(z)");
+    }
+
+    #[test]
+    fn only_actors_are_confused() {
+        assert_type_error("a uses sb casting confuse(p)", "Only actors can be confused.");
+        assert_no_diagnostics("a uses sb casting confuse(a)");
+        assert_no_diagnostics("a uses sb casting confuse(z)");
+    }
+
+    #[test]
+    fn only_actors_are_offered_to_a_time_warp() {
+        assert_type_error("a uses sb casting time_warp(m)\nend", "Only characters can be offered to a time warp.");
+        assert_no_diagnostics("a uses sb casting time_warp(counter)\nend");
+    }
+
+    #[test]
+    fn only_potions_are_created() {
+        assert_type_error("a uses sb casting create_potion(sb)", "Only potions can be created.");
+        assert_type_error("a uses sb casting create_potion(a)", "Only potions can be created.");
+        assert_no_diagnostics("a uses sb casting create_potion(p)");
+    }
+
+    #[test]
+    fn time_warp_bodies_are_checked() {
+        let statement = "a uses sb casting time_warp(counter)\n\ta uses sb casting confuse(m)\nend";
+        let (source, diagnostics) = check_statement(statement);
+        assert_eq!(
+            diagnostics.iter().map(summary).collect::<Vec<_>>(),
+            [(ErrorKind::Type, Severity::Error, "Only actors can be confused.", Some(span(&source, "a uses sb casting confuse(m)")))]
+        );
+    }
+
+    /// Asserts that `statement`, the last of the statements, has a single diagnostic
+    fn assert_kind_diagnostic(statements: &str, statement: &str, severity: Severity, message: &str) {
+        let (source, diagnostics) = check_statement(statements);
+        let start = source.rfind(statement).unwrap();
+        let expected = [(ErrorKind::Type, severity, message, Some(start))];
+        let diagnostics: Vec<_> = diagnostics.iter().map(|d| (d.kind, d.severity, d.message.as_str(), d.span.map(|span| span.start))).collect();
+        assert_eq!(diagnostics, expected, "{statements}");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn zombies_speak_after_they_are_un_zombified() {
+        assert_kind_diagnostic("z shouts sb casting speak()", "z shouts", Severity::Error, "Only characters can speak.");
+        assert_kind_diagnostic("z whispers sb casting speak()", "z whispers", Severity::Error, "Only characters can speak.");
+        assert_no_diagnostics("a uses sb casting un_zombify(z)\nz shouts sb casting speak()");
+    }
+
+    #[test]
+    fn zombies_are_offered_to_a_time_warp_after_they_are_un_zombified() {
+        assert_kind_diagnostic("a uses sb casting time_warp(z)\nend", "a uses", Severity::Error, "Only characters can be offered to a time warp.");
+        assert_no_diagnostics("a uses sb casting un_zombify(z)\na uses sb casting time_warp(z)\nend");
+    }
+
+    #[test]
+    fn zombies_create_potions_after_they_are_un_zombified() {
+        assert_kind_diagnostic("z uses sb casting create_potion(p)", "z uses", Severity::Error, "Only characters can create potions.");
+        assert_no_diagnostics("a uses sb casting un_zombify(z)\nz uses sb casting create_potion(p)");
+    }
+
+    #[test]
+    fn characters_are_not_un_zombified() {
+        assert_kind_diagnostic("a uses sb casting un_zombify(a)", "a uses", Severity::Error, "Only zombies can be un_zombified.");
+        assert_kind_diagnostic(
+            "a uses sb casting un_zombify(z)\na uses sb casting un_zombify(z)",
+            "a uses sb casting un_zombify(z)",
+            Severity::Error,
+            "Only zombies can be un_zombified."
+        );
+    }
+
+    #[test]
+    fn zombie_un_zombified_in_a_time_warp_may_be_either() {
+        let (source, diagnostics) = check_statement("a uses sb casting time_warp(counter)\n\ta uses sb casting un_zombify(z)\nend\nz shouts sb casting speak()");
+        assert_eq!(diagnostics.iter().map(summary).collect::<Vec<_>>(), [
+            // The body runs more than once, so the zombie is un_zombified again in the next iteration
+            (ErrorKind::Type, Severity::Warning, "Only zombies can be un_zombified, but the actor may already be a character.",
+                Some(span(&source, "a uses sb casting un_zombify(z)"))),
+            // The body may not run at all
+            (ErrorKind::Type, Severity::Warning, "Only characters can speak, but the actor may still be a zombie.",
+                Some(span(&source, "z shouts sb casting speak()")))
+        ]);
+    }
+}
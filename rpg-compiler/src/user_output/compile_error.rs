@@ -1,71 +1,41 @@
-use std::io;
-use std::num::ParseIntError;
+use std::fmt::Display;
+use crate::user_output::Diagnostic;
+
 #[macro_export]
-/// Prints out an compilation error message in red.
+/// Returns a [`Diagnostic`](crate::user_output::Diagnostic) of the given
+/// [`ErrorKind`](crate::user_output::ErrorKind) from the current function.
+///
+/// The span of the error can optionally be given before the message:
+/// `compile_error!(Type, action.span; "message")`
 macro_rules! compile_error {
-    ($( $arg: tt)*) => ({
-        let s = format!($($arg)*);
-        eprintln!("{}", simple_colors::red!(s));
-        std::process::exit(1)
+    ($kind: ident, $span: expr; $( $arg: tt)*) => ({
+        return Err($crate::user_output::Diagnostic::new(
+            $crate::user_output::ErrorKind::$kind,
+            format!($($arg)*)
+        ).with_span($span))
+    });
+    ($kind: ident, $( $arg: tt)*) => ({
+        return Err($crate::user_output::Diagnostic::new(
+            $crate::user_output::ErrorKind::$kind,
+            format!($($arg)*)
+        ))
     })
 }
 
-pub static mut VERBOSE: bool = false;
-
 pub trait CompileError<T> {
-    fn expect_compile_error(self, msg: &str) -> T;
+    /// Turns the error into the given diagnostic, keeping the original error as the cause of the
+    /// diagnostic.
+    fn or_compile_error(self, diagnostic: Diagnostic) -> Result<T, Diagnostic>;
 }
 
-impl<T> CompileError<T> for Result<T, ParseIntError> {
-    fn expect_compile_error(self, msg: &str) -> T {
-        match self {
-            Ok(t) => t,
-            Err(e) => unsafe {
-                if VERBOSE {
-                    crate::compile_error!("{}\n== VERBOSE OUTPUT ==\n{}", msg, e)
-                } else {
-                    crate::compile_error!("{}", msg)
-                }
-            }
-        }
-    }
-}
-
-impl<T> CompileError<T> for Result<T, String> {
-    fn expect_compile_error(self, msg: &str) -> T {
-        match self {
-            Ok(t) => t,
-            Err(e) => unsafe {
-                if VERBOSE {
-                    crate::compile_error!("{}\n== VERBOSE OUTPUT ==\n{}", msg, e)
-                } else {
-                    crate::compile_error!("{}", msg)
-                }
-            }
-        }
+impl<T, E: Display> CompileError<T> for Result<T, E> {
+    fn or_compile_error(self, diagnostic: Diagnostic) -> Result<T, Diagnostic> {
+        self.map_err(|e| diagnostic.with_cause(e.to_string()))
     }
 }
 
 impl<T> CompileError<T> for Option<T> {
-    fn expect_compile_error(self, msg: &str) -> T {
-        match self {
-            Some(t) => t,
-            None => crate::compile_error!("{}", msg)
-        }
+    fn or_compile_error(self, diagnostic: Diagnostic) -> Result<T, Diagnostic> {
+        self.ok_or(diagnostic)
     }
 }
-
-impl<T> CompileError<T> for io::Result<T> {
-    fn expect_compile_error(self, msg: &str) -> T {
-        match self {
-            Ok(t) => t,
-            Err(e) => unsafe {
-                if VERBOSE {
-                    crate::compile_error!("{}\n== VERBOSE OUTPUT ==\n{}", msg, e)
-                } else {
-                    crate::compile_error!("{}", msg)
-                }
-            }
-        }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,253 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use simple_colors::{blue, cyan, red, yellow};
+
+/// The category of a [`Diagnostic`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The source file could not be read
+    Io,
+    /// The source code contains something that is not a valid token
+    UnknownToken,
+    /// The tokens do not form a valid statement
+    Syntax,
+    /// A name is used that was never declared
+    UndefinedName,
+    /// A declaration hides an earlier declaration with the same name
+    Shadowing,
+    /// A literal value is out of range for the thing it is assigned to
+    InvalidValue,
+    /// An object is used in a place where another kind of object is expected
+    Type,
+    /// An actor uses an item that is not in its inventory
+    Inventory,
+    /// An actor is used after it died
+    DeadActor,
+    /// An error occurred while running the program
+    Runtime,
+    /// A bug in the compiler
+    Internal
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Io => "io",
+            Self::UnknownToken => "unknown token",
+            Self::Syntax => "syntax",
+            Self::UndefinedName => "undefined name",
+            Self::Shadowing => "shadowing",
+            Self::InvalidValue => "invalid value",
+            Self::Type => "type",
+            Self::Inventory => "inventory",
+            Self::DeadActor => "dead actor",
+            Self::Runtime => "runtime",
+            Self::Internal => "internal",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Whether a [`Diagnostic`] stops the compilation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    /// Something that might go wrong at runtime, the program is still compiled
+    Warning
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning")
+        }
+    }
+}
+
+/// A region of the source code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    /// Byte offset of the start of the region
+    pub start: usize,
+    /// Byte offset of the end of the region (exclusive)
+    pub end: usize,
+    /// The line of `start`, starting at 1
+    pub line: usize,
+    /// The column of `start` in characters, starting at 1
+    pub column: usize
+}
+
+impl Span {
+    /// Returns a span from the start of `self` to the end of `other`
+    pub fn join(self, other: Span) -> Span {
+        Span { end: other.end, ..self }
+    }
+    
+    /// Quotes the line of the span in `source` and underlines the span. A span covering multiple
+    /// lines is underlined until the end of its first line.
+    pub fn snippet(&self, source: &str) -> String {
+        let line_start = source.split('\n').take(self.line.saturating_sub(1))
+            .map(|line| line.len() + 1)
+            .sum::<usize>()
+            .min(source.len());
+        let line = source[line_start..].split('\n').next().unwrap_or("").trim_end_matches('\r');
+        let prefix: String = line.chars().take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underlined = source.get(self.start.max(line_start)..self.end.min(line_start + line.len()))
+            .map(|s| s.chars().count())
+            .unwrap_or(0)
+            .max(1);
+        let gutter = " ".repeat(self.line.to_string().len());
+        format!("{gutter} |\n{} | {}\n{gutter} | {}{}", self.line, line, prefix, "^".repeat(underlined))
+    }
+}
+
+/// Another location in the source code that is related to a [`Diagnostic`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub span: Span
+}
+
+/// An error or warning found while compiling a program. The fields are boxed, so results that
+/// return a diagnostic stay small.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic(Box<DiagnosticData>);
+
+/// The fields of a [`Diagnostic`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticData {
+    pub kind: ErrorKind,
+    pub severity: Severity,
+    pub message: String,
+    /// A suggestion on how to fix the error
+    pub hint: Option<String>,
+    /// The underlying error, only kept when compiling in verbose mode
+    pub cause: Option<String>,
+    /// Where in the source code the error occurred
+    pub span: Option<Span>,
+    /// Related locations, e.g. the earlier declaration of a shadowed name
+    pub notes: Vec<Note>
+}
+
+impl Deref for Diagnostic {
+    type Target = DiagnosticData;
+
+    fn deref(&self) -> &DiagnosticData {
+        &self.0
+    }
+}
+
+impl DerefMut for Diagnostic {
+    fn deref_mut(&mut self) -> &mut DiagnosticData {
+        &mut self.0
+    }
+}
+
+impl Diagnostic {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self(Box::new(DiagnosticData {
+            kind,
+            severity: Severity::Error,
+            message: message.into(),
+            hint: None,
+            cause: None,
+            span: None,
+            notes: Vec::new()
+        }))
+    }
+
+    pub fn warning(kind: ErrorKind, message: impl Into<String>) -> Self {
+        let mut diagnostic = Self::new(kind, message);
+        diagnostic.severity = Severity::Warning;
+        diagnostic
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn with_cause(mut self, cause: impl Into<String>) -> Self {
+        self.cause = Some(cause.into());
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.notes.push(Note { message: message.into(), span });
+        self
+    }
+}
+
+impl Diagnostic {
+    pub fn is_warning(&self) -> bool {
+        self.severity == Severity::Warning
+    }
+
+    /// The result of a phase that reported the diagnostics: the warnings when none of them is an
+    /// error, all diagnostics otherwise
+    pub fn ok_if_warnings(diagnostics: Vec<Diagnostic>) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        if diagnostics.iter().all(Diagnostic::is_warning) {
+            Ok(diagnostics)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    /// Returns the location of the error as `file:line:column`
+    pub fn location(&self, file: &str) -> Option<String> {
+        self.span.map(|span| format!("{}:{}:{}", file, span.line, span.column))
+    }
+    
+    /// Renders the diagnostic in color for a terminal, along with the lines of `source` it and
+    /// its notes point to
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let mut s = if self.is_warning() {
+            format!("{}\n{}", yellow!("Warning"), yellow!(&self.message))
+        } else {
+            let title = if self.kind == ErrorKind::Runtime { "Runtime error" } else { "Compile error" };
+            format!("{}\n{}", cyan!(title), red!(&self.message))
+        };
+        if let (Some(location), Some(span)) = (self.location(file), self.span) {
+            s.push_str(&format!("\n {} {}\n{}", blue!("-->"), location, span.snippet(source)));
+        }
+        for note in &self.notes {
+            s.push_str(&format!(
+                "\n{} {}\n {} {}:{}:{}\n{}",
+                blue!("NOTE:"), note.message, blue!("-->"), file, note.span.line, note.span.column, note.span.snippet(source)
+            ));
+        }
+        if let Some(hint) = &self.hint {
+            s.push_str(&format!("\n{} {}", blue!("HINT:"), hint));
+        }
+        if let Some(cause) = &self.cause {
+            s.push_str(&format!("\n== VERBOSE OUTPUT ==\n{}", cause));
+        }
+        s
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.kind, self.severity, self.message)?;
+        for note in &self.notes {
+            write!(f, "\nNOTE: {} (line {}, column {})", note.message, note.span.line, note.span.column)?;
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, "\nHINT: {}", hint)?;
+        }
+        if let Some(cause) = &self.cause {
+            write!(f, "\n== VERBOSE OUTPUT ==\n{}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -1,4 +1,6 @@
 //! Contains functions and macros for informing the user about events
 
 mod compile_error;
-pub use compile_error::*;
\ No newline at end of file
+pub use compile_error::*;
+mod diagnostic;
+pub use diagnostic::*;
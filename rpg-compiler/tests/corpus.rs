@@ -0,0 +1,103 @@
+//! Runs every program in `tests/corpus` using the interpreter and as a compiled binary, and
+//! compares the output with the `.out` file next to it.
+//!
+//! The programs cast spells inside time warps, which have to change the same objects as when
+//! they are cast outside of a time warp.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use rpg_compiler::interpreter::Interpreter;
+use rpg_compiler::{compile_str, Config, Parser, Tokenizer};
+
+fn programs() -> Vec<PathBuf> {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus");
+    let mut programs: Vec<PathBuf> = fs::read_dir(corpus).expect("The corpus exists")
+        .map(|entry| entry.expect("The corpus can be read").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "rpg"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn expected_output(program: &Path) -> String {
+    fs::read_to_string(program.with_extension("out")).expect("Every program has an .out file")
+}
+
+fn interpret(source: &str) -> String {
+    let mut tokens = Tokenizer::new(source).tokenize().expect("The program can be tokenized");
+    let parsed = Parser::new(&mut tokens).parse().expect("The program can be parsed");
+    let mut output = Vec::new();
+    Interpreter::new(&parsed, 10, &b""[..], &mut output).run().expect("The program runs without errors");
+    String::from_utf8(output).expect("The output is utf-8")
+}
+
+fn rustc() -> Command {
+    let mut rustc = Command::new(option_env!("RUSTC").unwrap_or("rustc"));
+    // The same edition as the cargo project of the cli
+    rustc.args(["--edition", "2021"]);
+    rustc
+}
+
+/// Compiles the `rpg-runtime` crate once, returns the path of the library the generated code
+/// is linked to
+fn runtime(dir: &Path) -> &'static Path {
+    static RUNTIME: OnceLock<PathBuf> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        let lib = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("rpg-runtime").join("src").join("lib.rs");
+        let rustc = rustc()
+            .args(["--crate-type", "lib", "--crate-name", "rpg_runtime"])
+            .arg(&lib)
+            .arg("--out-dir")
+            .arg(dir)
+            .output()
+            .expect("rustc can be run");
+        assert!(rustc.status.success(), "The runtime doesn't compile:\n{}", String::from_utf8_lossy(&rustc.stderr));
+        dir.join("librpg_runtime.rlib")
+    })
+}
+
+/// Compiles the generated code using rustc and runs the binary
+fn run_compiled(name: &str, source: &str, config: Config) -> String {
+    let compiled = compile_str(source, name, config).expect("The program compiles");
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("corpus");
+    fs::create_dir_all(&dir).expect("The temporary directory can be created");
+    let runtime = runtime(&dir);
+    let stem = format!("{name}_{}", if config.fold { "fold" } else { "no_fold" });
+    let main = dir.join(format!("{stem}.rs"));
+    let binary = dir.join(&stem);
+    fs::write(&main, compiled.code).expect("The generated code can be written");
+    let rustc = rustc()
+        .arg("--extern")
+        .arg(format!("rpg_runtime={}", runtime.display()))
+        .arg(&main)
+        .arg("-o")
+        .arg(&binary)
+        .output()
+        .expect("rustc can be run");
+    assert!(rustc.status.success(), "The generated code of {name} doesn't compile:\n{}", String::from_utf8_lossy(&rustc.stderr));
+    let run = Command::new(&binary).output().expect("The binary can be run");
+    assert!(run.status.success(), "{name} failed:\n{}", String::from_utf8_lossy(&run.stderr));
+    String::from_utf8(run.stdout).expect("The output is utf-8")
+}
+
+#[test]
+fn interpreter_output() {
+    for program in programs() {
+        let source = fs::read_to_string(&program).expect("The program can be read");
+        assert_eq!(interpret(&source), expected_output(&program), "{}", program.display());
+    }
+}
+
+#[test]
+fn compiled_output() {
+    for program in programs() {
+        let name = program.file_stem().expect("The program has a name").to_string_lossy().into_owned();
+        let source = fs::read_to_string(&program).expect("The program can be read");
+        for fold in [false, true] {
+            let output = run_compiled(&name, &source, Config { fold, ..Config::default() });
+            assert_eq!(output, expected_output(&program), "{name} (fold: {fold})");
+        }
+    }
+}
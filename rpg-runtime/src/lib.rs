@@ -0,0 +1,419 @@
+//! The runtime of rpg programs.
+//!
+//! Compiled programs declare their objects in a `Game` and call a method for every statement.
+//! The interpreter of the compiler runs programs on the same `Game`, so both behave the same.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead, StdinLock, Stdout, Write};
+
+/// The source code of this crate. The cli writes it next to the generated code, so programs are
+/// built with the runtime the cli was built with, without downloading it.
+pub const SOURCE: &str = include_str!("lib.rs");
+
+macro_rules! red {
+    ( $other: expr ) => ({
+        format!("\x1b[31m{}\x1b[0m", $other)
+    })
+}
+
+macro_rules! blue {
+    ( $other: expr ) => ({
+        format!("\x1b[34m{}\x1b[0m", $other)
+    })
+}
+
+macro_rules! cyan {
+    ( $other: expr ) => ({
+        format!("\x1b[36m{}\x1b[0m", $other)
+    })
+}
+
+/// Creates a `RuntimeError` with a formatted message
+macro_rules! runtime_error {
+    ($( $arg: tt)*) => ({
+        RuntimeError::new(format!($($arg)*))
+    })
+}
+
+/// The reason a program can't continue
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub hint: Option<String>
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into(), hint: None }
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\nHINT: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for RuntimeError {}
+
+/// Either a char or a zombie
+#[derive(Debug, Clone, PartialEq)]
+pub struct Actor {
+    /// Can derive actor type using `health`
+    pub health: ActorHealth,
+    pub attack: u32,
+    pub items: Vec<Item>,
+    pub confused: bool
+}
+
+impl Actor {
+    pub fn new(health: ActorHealth, attack: u32) -> Self {
+        Self { health, attack, items: Vec::new(), confused: false }
+    }
+
+    /// The health as seen by the outside world, confused actors lie about their health.
+    pub fn health(&self) -> Result<ActorHealth, RuntimeError> {
+        if !self.confused {
+            return Ok(self.health);
+        }
+        match self.health {
+            ActorHealth::Char(v) => v.checked_sub(1).map(ActorHealth::Char),
+            ActorHealth::Zombie(v) => v.checked_sub(1).map(ActorHealth::Zombie)
+        }.ok_or_else(|| runtime_error!("The health of a confused actor overflowed."))
+    }
+
+    /// Characters with no health left are not valid
+    pub fn validate_actor(&self) -> bool {
+        if let ActorHealth::Char(val) = self.health { val != 0 } else { true }
+    }
+
+    pub fn owns_spellbook(&self) -> bool {
+        self.items.contains(&Item::SpellBook)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActorHealth {
+    Char(u32),
+    Zombie(i32)
+}
+
+impl Display for ActorHealth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ActorHealth::Char(val) => write!(f, "{}", val),
+            ActorHealth::Zombie(val) => write!(f, "{}", val)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Item {
+    /// (id, healing_value)
+    Potion(usize, u32),
+    SpellBook
+}
+
+/// All objects of a program, indexed by the id of their declaration
+pub struct Game<R = StdinLock<'static>, W = Stdout> {
+    /// All actors that were ever declared
+    pub actors: HashMap<usize, Actor>,
+    /// The current healing value of every potion
+    pub potions: HashMap<usize, u32>,
+    /// The ids of all actors that are alive, in order of creation
+    pub alive: Vec<usize>,
+    /// The maximum amount of characters allowed in the program
+    pub max_chars: usize,
+    /// Read from by `god_speech()`
+    input: R,
+    /// Written to by `shouts` and `whispers`
+    output: W,
+    /// The location of the statement that is currently executing, shown by `exit`
+    location: &'static str
+}
+
+impl Game {
+    /// A game that reads from stdin and writes to stdout
+    pub fn new(max_chars: usize) -> Self {
+        Self::with_io(max_chars, io::stdin().lock(), io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> Game<R, W> {
+    pub fn with_io(max_chars: usize, input: R, output: W) -> Self {
+        Self {
+            actors: HashMap::new(),
+            potions: HashMap::new(),
+            alive: Vec::new(),
+            max_chars,
+            input,
+            output,
+            location: ""
+        }
+    }
+
+    /// Sets the location of the statement that is executed next, e.g. ` --> main.rpg:1:1`
+    pub fn at(&mut self, location: &'static str) {
+        self.location = location;
+    }
+
+    /// Prints the error and the location of the statement that caused it, then exits the process
+    pub fn exit(&mut self, error: RuntimeError) -> ! {
+        let _ = self.output.flush();
+        eprintln!("{}\n{}", cyan!("Runtime error"), red!(error.message));
+        if !self.location.is_empty() {
+            eprintln!("{}", self.location);
+        }
+        if let Some(hint) = error.hint {
+            eprintln!("{} {}", blue!("HINT:"), hint);
+        }
+        std::process::exit(1)
+    }
+
+    /// Consumes the game, returning everything the program wrote
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    pub fn flush(&mut self) -> Result<(), RuntimeError> {
+        self.output.flush().map_err(|e| runtime_error!("Could not write output: {e}"))
+    }
+
+    pub fn write(&mut self, s: &str) -> Result<(), RuntimeError> {
+        self.output.write_all(s.as_bytes()).map_err(|e| runtime_error!("Could not write output: {e}"))
+    }
+
+    /// Declares the actor, a declaration that runs again replaces the actor it declared before
+    pub fn add_actor(&mut self, id: usize, actor: Actor) -> Result<(), RuntimeError> {
+        self.actors.insert(id, actor);
+        if !self.alive.contains(&id) {
+            self.alive.push(id);
+        }
+        if self.alive.len() > self.max_chars {
+            return Err(runtime_error!("Your actors exceeded the maximum amount of actors allowed ({})", self.max_chars)
+                .with_hint(format!("Actors alive: {:?}", self.alive)));
+        }
+        Ok(())
+    }
+
+    pub fn add_potion(&mut self, id: usize, value: u32) {
+        self.potions.insert(id, value);
+    }
+
+    fn rm_actor(&mut self, id: usize) {
+        self.alive.retain(|a| a != &id);
+    }
+
+    /// Removes the actors declared in the body of a time warp at the end of an iteration, they
+    /// can't be used after it
+    pub fn remove_actors(&mut self, ids: &[usize]) {
+        for id in ids {
+            self.actors.remove(id);
+            self.rm_actor(*id);
+        }
+    }
+
+    pub fn actor(&mut self, id: usize) -> Result<&mut Actor, RuntimeError> {
+        self.actors.get_mut(&id).ok_or_else(|| runtime_error!("The actor does not exist."))
+    }
+
+    /// Returns the item with the given id in its current state
+    pub fn item(&self, id: usize) -> Item {
+        if let Some(value) = self.potions.get(&id) {
+            Item::Potion(id, *value)
+        } else {
+            Item::SpellBook
+        }
+    }
+
+    pub fn buys(&mut self, user: usize, item: usize) -> Result<(), RuntimeError> {
+        if !self.alive.contains(&user) {
+            return Err(runtime_error!("Cannot add an item to the inventory of a dead actor."));
+        }
+        let item = self.item(item);
+        self.actor(user)?.items.push(item);
+        Ok(())
+    }
+
+    pub fn attacks(&mut self, attacker: usize, attacked: usize) -> Result<(), RuntimeError> {
+        if !self.alive.contains(&attacker) {
+            return Err(runtime_error!("A dead actor cannot attack."));
+        }
+        let attack = self.actor(attacker)?.attack;
+        self.attacked(attacked, attack)
+    }
+
+    pub fn uses(&mut self, user: usize, potion: usize) -> Result<(), RuntimeError> {
+        if let Item::Potion(potion, heal) = self.item(potion) {
+            if self.alive.contains(&user) {
+                let actor = self.actor(user)?;
+                actor.health = match actor.health {
+                    ActorHealth::Char(0) => return Err(runtime_error!("Cannot heal a dead actor.")),
+                    ActorHealth::Char(val) => val.checked_add(heal).map(ActorHealth::Char),
+                    ActorHealth::Zombie(val) => i32::try_from(heal).ok()
+                        .and_then(|heal| val.checked_add(heal))
+                        .map(ActorHealth::Zombie)
+                }.ok_or_else(|| runtime_error!("The health of the actor overflowed."))?;
+                let item_index = actor.items.iter().position(|item| matches!(item, Item::Potion(id, _) if *id == potion))
+                    .ok_or_else(|| runtime_error!("The actor does not own the potion it is trying to use."))?;
+                actor.items.remove(item_index);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn shouts(&mut self, user: usize) -> Result<(), RuntimeError> {
+        if !self.alive.contains(&user) {
+            return Err(runtime_error!("Dead actors can't shout."));
+        }
+        let health = self.actor(user)?.health()?;
+        self.write(&format!("{}\n", health))
+    }
+
+    pub fn shouts_speak(&mut self, user: usize) -> Result<(), RuntimeError> {
+        self.expect_spellbook(user)?;
+        if !self.alive.contains(&user) {
+            return Err(runtime_error!("Dead actors can't shout."));
+        }
+        if let ActorHealth::Char(val) = self.actor(user)?.health()? {
+            self.write(&format!("{}\n", (val as u8) as char))
+        } else {
+            Err(runtime_error!("Wrong type, only characters can shout speak."))
+        }
+    }
+
+    pub fn whispers(&mut self, user: usize) -> Result<(), RuntimeError> {
+        let actor = self.actor(user)?;
+        if !actor.validate_actor() {
+            return Err(runtime_error!("Dead actors can't shout."));
+        }
+        let health = actor.health()?;
+        self.write(&health.to_string())
+    }
+
+    pub fn whispers_speak(&mut self, user: usize) -> Result<(), RuntimeError> {
+        self.expect_spellbook(user)?;
+        let actor = self.actor(user)?;
+        if !actor.validate_actor() {
+            return Err(runtime_error!("Dead actors can't shout."));
+        }
+        if let ActorHealth::Char(val) = actor.health()? {
+            self.write(&((val as u8) as char).to_string())
+        } else {
+            Err(runtime_error!("Wrong type, only characters can whisper speak."))
+        }
+    }
+
+    /// Every spell has to be cast by an actor that owns a spellbook
+    pub fn expect_spellbook(&mut self, user: usize) -> Result<(), RuntimeError> {
+        if self.actor(user)?.owns_spellbook() {
+            Ok(())
+        } else {
+            Err(runtime_error!("The spell cannot be called, because the caster doesn't own a spellbook."))
+        }
+    }
+
+    pub fn un_zombify(&mut self, id: usize) -> Result<(), RuntimeError> {
+        let zombie = self.actor(id)?;
+        if let ActorHealth::Zombie(h) = zombie.health {
+            if h <= 0 {
+                self.rm_actor(id);
+            } else {
+                *zombie = Actor::new(ActorHealth::Char(h as u32), zombie.attack);
+            }
+            Ok(())
+        } else {
+            Err(runtime_error!("Tried to call `un_zombify` on a non-zombie."))
+        }
+    }
+
+    pub fn confuse(&mut self, id: usize) -> Result<(), RuntimeError> {
+        self.actor(id)?.confused = true;
+        Ok(())
+    }
+
+    /// Sets the health of the actor to the number on the next line of the input
+    pub fn god_speech(&mut self, user: usize) -> Result<(), RuntimeError> {
+        self.flush()?;
+        let mut s = String::new();
+        self.input.read_line(&mut s).map_err(|_| runtime_error!("Input invalid."))?;
+        let s = s.strip_suffix('\n').unwrap_or(&s);
+        let s = s.strip_suffix('\r').unwrap_or(s);
+        let actor = self.actor(user)?;
+        actor.health = match actor.health {
+            ActorHealth::Char(_) => s.parse::<u32>().map(ActorHealth::Char).ok(),
+            ActorHealth::Zombie(_) => s.parse::<i32>().map(ActorHealth::Zombie).ok()
+        }.ok_or_else(|| runtime_error!("Invalid input"))?;
+        Ok(())
+    }
+
+    /// The amount of iterations left in a time warp consuming the actor
+    pub fn loop_times(&mut self, consumed: usize) -> Result<u32, RuntimeError> {
+        match self.actor(consumed)?.health {
+            ActorHealth::Char(val) => Ok(val),
+            ActorHealth::Zombie(_) => Err(runtime_error!("Zombies don't like loops."))
+        }
+    }
+
+    pub fn shift(&mut self, user: usize) -> Result<(), RuntimeError> {
+        let actor = self.actor(user)?;
+        if let ActorHealth::Char(attack) = actor.health {
+            actor.health = ActorHealth::Char(actor.attack);
+            actor.attack = attack;
+        }
+        Ok(())
+    }
+
+    pub fn create_potion(&mut self, user: usize, potion: usize) -> Result<(), RuntimeError> {
+        let old = self.item(potion);
+        let actor = self.actor(user)?;
+        let potion_index = actor.items.iter().position(|item| item == &old)
+            .ok_or_else(|| runtime_error!("The actor does not own the potion it is trying to create."))?;
+        actor.items.remove(potion_index);
+        let health = if let ActorHealth::Char(h) = actor.health {
+            h
+        } else {
+            return Err(runtime_error!("Only actors can make potions."));
+        };
+        actor.items.push(Item::Potion(potion, health));
+        self.potions.insert(potion, health);
+        Ok(())
+    }
+
+    /// Subtracts `attack` from the health of the actor, characters die when their health reaches 0
+    pub fn attacked(&mut self, id: usize, attack: u32) -> Result<(), RuntimeError> {
+        let actor = self.actor(id)?;
+        match &mut actor.health {
+            ActorHealth::Char(0) => {
+                self.write("Stop beating a dead corpse.\n")?;
+            }
+            ActorHealth::Char(val) if *val <= attack => {
+                *val = 0;
+                if !self.alive.contains(&id) {
+                    return Err(runtime_error!("The now deceased actor was never alive in the first place."));
+                }
+                self.rm_actor(id);
+            }
+            ActorHealth::Char(val) => {
+                *val -= attack;
+            }
+            ActorHealth::Zombie(val) => {
+                *val = i32::try_from(attack).ok()
+                    .and_then(|attack| val.checked_sub(attack))
+                    .ok_or_else(|| runtime_error!("The health of the zombie overflowed."))?;
+            }
+        }
+        Ok(())
+    }
+}
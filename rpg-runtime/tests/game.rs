@@ -0,0 +1,205 @@
+//! Runs statements on a `Game` with in-memory input and output.
+
+use std::io::Cursor;
+use rpg_runtime::{Actor, ActorHealth, Game, Item, RuntimeError};
+
+type TestGame = Game<Cursor<&'static str>, Vec<u8>>;
+
+fn game(input: &'static str) -> TestGame {
+    Game::with_io(10, Cursor::new(input), Vec::new())
+}
+
+fn output(game: TestGame) -> String {
+    String::from_utf8(game.into_output()).expect("The output is utf-8")
+}
+
+const SPELLBOOK: usize = 100;
+const POTION: usize = 101;
+
+/// Declares a character that owns a spellbook
+fn caster(game: &mut TestGame, id: usize, health: u32) {
+    game.add_actor(id, Actor::new(ActorHealth::Char(health), 1)).unwrap();
+    game.buys(id, SPELLBOOK).unwrap();
+}
+
+#[test]
+fn shouts_and_whispers_the_health() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Char(5), 0)).unwrap();
+    game.add_actor(2, Actor::new(ActorHealth::Zombie(-3), 0)).unwrap();
+    game.shouts(1).unwrap();
+    game.whispers(2).unwrap();
+    game.whispers(1).unwrap();
+    assert_eq!(output(game), "5\n-35");
+}
+
+#[test]
+fn confused_actors_lie_about_their_health() {
+    let mut game = game("");
+    caster(&mut game, 1, 5);
+    game.confuse(1).unwrap();
+    game.shouts(1).unwrap();
+    assert_eq!(game.actors[&1].health, ActorHealth::Char(5));
+    assert_eq!(output(game), "4\n");
+}
+
+#[test]
+fn speak_prints_the_health_as_ascii() {
+    let mut game = game("");
+    caster(&mut game, 1, 72);
+    caster(&mut game, 2, 105);
+    game.whispers_speak(1).unwrap();
+    game.shouts_speak(2).unwrap();
+    assert_eq!(output(game), "Hi\n");
+}
+
+#[test]
+fn zombies_can_not_speak() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Zombie(72), 0)).unwrap();
+    game.buys(1, SPELLBOOK).unwrap();
+    assert_eq!(game.shouts_speak(1), Err(RuntimeError::new("Wrong type, only characters can shout speak.")));
+}
+
+#[test]
+fn characters_die_when_their_health_reaches_zero() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Char(10), 3)).unwrap();
+    game.add_actor(2, Actor::new(ActorHealth::Char(2), 5)).unwrap();
+    game.attacks(1, 2).unwrap();
+    assert_eq!(game.alive, vec![1]);
+    assert_eq!(game.shouts(2), Err(RuntimeError::new("Dead actors can't shout.")));
+    assert_eq!(game.attacks(2, 1), Err(RuntimeError::new("A dead actor cannot attack.")));
+    game.attacks(1, 2).unwrap();
+    assert_eq!(output(game), "Stop beating a dead corpse.\n");
+}
+
+#[test]
+fn zombies_survive_negative_health() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Char(10), 3)).unwrap();
+    game.add_actor(2, Actor::new(ActorHealth::Zombie(1), 0)).unwrap();
+    game.attacks(1, 2).unwrap();
+    assert_eq!(game.actors[&2].health, ActorHealth::Zombie(-2));
+    assert_eq!(game.alive, vec![1, 2]);
+}
+
+#[test]
+fn potions_heal_once_per_purchase() {
+    let mut game = game("");
+    game.add_potion(POTION, 5);
+    game.add_actor(1, Actor::new(ActorHealth::Char(5), 0)).unwrap();
+    game.buys(1, POTION).unwrap();
+    game.uses(1, POTION).unwrap();
+    assert_eq!(game.actors[&1].health, ActorHealth::Char(10));
+    assert_eq!(game.uses(1, POTION), Err(RuntimeError::new("The actor does not own the potion it is trying to use.")));
+}
+
+#[test]
+fn create_potion_sets_the_value_of_every_copy() {
+    let mut game = game("");
+    game.add_potion(POTION, 5);
+    caster(&mut game, 1, 6);
+    game.buys(1, POTION).unwrap();
+    game.create_potion(1, POTION).unwrap();
+    assert_eq!(game.item(POTION), Item::Potion(POTION, 6));
+    game.uses(1, POTION).unwrap();
+    assert_eq!(game.actors[&1].health, ActorHealth::Char(12));
+}
+
+#[test]
+fn un_zombify_turns_a_zombie_into_a_character() {
+    let mut game = game("");
+    caster(&mut game, 1, 1);
+    game.add_actor(2, Actor::new(ActorHealth::Zombie(72), 4)).unwrap();
+    game.un_zombify(2).unwrap();
+    assert_eq!(game.actors[&2], Actor::new(ActorHealth::Char(72), 4));
+    assert_eq!(game.un_zombify(2), Err(RuntimeError::new("Tried to call `un_zombify` on a non-zombie.")));
+}
+
+#[test]
+fn un_zombify_removes_a_zombie_without_health() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Zombie(0), 4)).unwrap();
+    game.un_zombify(1).unwrap();
+    assert!(game.alive.is_empty());
+}
+
+#[test]
+fn shift_swaps_health_and_attack() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Char(2), 1)).unwrap();
+    game.shift(1).unwrap();
+    assert_eq!(game.actors[&1].health, ActorHealth::Char(1));
+    assert_eq!(game.actors[&1].attack, 2);
+}
+
+#[test]
+fn time_warps_loop_until_the_consumed_character_has_no_health() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Char(3), 0)).unwrap();
+    while game.loop_times(1).unwrap() != 0 {
+        game.whispers(1).unwrap();
+        game.attacked(1, 1).unwrap();
+    }
+    assert_eq!(output(game), "321");
+}
+
+#[test]
+fn zombies_dont_like_loops() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Zombie(3), 0)).unwrap();
+    assert_eq!(game.loop_times(1), Err(RuntimeError::new("Zombies don't like loops.")));
+}
+
+#[test]
+fn god_speech_reads_a_line() {
+    let mut game = game("42\r\n-7\nnot a number\n");
+    caster(&mut game, 1, 0);
+    game.add_actor(2, Actor::new(ActorHealth::Zombie(0), 0)).unwrap();
+    game.god_speech(1).unwrap();
+    game.god_speech(2).unwrap();
+    assert_eq!(game.actors[&1].health, ActorHealth::Char(42));
+    assert_eq!(game.actors[&2].health, ActorHealth::Zombie(-7));
+    assert_eq!(game.god_speech(1), Err(RuntimeError::new("Invalid input")));
+}
+
+#[test]
+fn spells_need_a_spellbook() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Char(1), 0)).unwrap();
+    assert_eq!(
+        game.expect_spellbook(1),
+        Err(RuntimeError::new("The spell cannot be called, because the caster doesn't own a spellbook."))
+    );
+}
+
+#[test]
+fn too_many_actors() {
+    let mut game = Game::with_io(1, Cursor::new(""), Vec::new());
+    game.add_actor(1, Actor::new(ActorHealth::Char(1), 0)).unwrap();
+    assert_eq!(
+        game.add_actor(2, Actor::new(ActorHealth::Char(1), 0)),
+        Err(RuntimeError::new("Your actors exceeded the maximum amount of actors allowed (1)").with_hint("Actors alive: [1, 2]"))
+    );
+}
+
+#[test]
+fn declaring_an_actor_again_replaces_it() {
+    let mut game = Game::with_io(1, Cursor::new(""), Vec::new());
+    game.add_actor(1, Actor::new(ActorHealth::Char(1), 0)).unwrap();
+    game.add_actor(1, Actor::new(ActorHealth::Char(2), 0)).unwrap();
+    assert_eq!(game.alive, [1]);
+    assert_eq!(game.actors[&1].health, ActorHealth::Char(2));
+}
+
+#[test]
+fn removed_actors_are_dead() {
+    let mut game = game("");
+    game.add_actor(1, Actor::new(ActorHealth::Char(1), 0)).unwrap();
+    game.add_actor(2, Actor::new(ActorHealth::Zombie(1), 0)).unwrap();
+    game.add_actor(3, Actor::new(ActorHealth::Char(1), 0)).unwrap();
+    game.remove_actors(&[1, 2]);
+    assert_eq!(game.alive, [3]);
+    assert!(!game.actors.contains_key(&1) && !game.actors.contains_key(&2));
+}